@@ -31,6 +31,19 @@ fn calculate_watts(
 
 
 
+/// Average cycling speed in m/s on a slope (rise over run)
+pub(crate) fn cycling_speed(slope: f64) -> f64 {
+    match slope {
+        (0.02..=0.1) => 6.0,
+        (0.1..=0.2) => 4.5,
+        (0.2..) => 3.5,
+        (-0.02..=0.02) => 6.26,
+        (-0.1..=0.0) => 6.6,
+        (..=-0.1) => 7.0,
+        _ => 6.0,
+    }
+}
+
 fn calculate_calories_for_edge(from: NodeIndex, to: NodeIndex, graph: &AGraph) -> Energy {
     let source = graph.node_weight(from).unwrap();
     let target = graph.node_weight(to).unwrap();
@@ -41,15 +54,7 @@ fn calculate_calories_for_edge(from: NodeIndex, to: NodeIndex, graph: &AGraph) -
     let elevation_diff = (target.ele - source.ele) as f64;
     let slope = elevation_diff / edge_dist;
 
-    let average_speed = match slope {
-        (0.02..=0.1) => 6.0,
-        (0.1..=0.2) => 4.5,
-        (0.2..) => 3.5,
-        (-0.02..=0.02) => 6.26,
-        (-0.1..=0.0) => 6.6,
-        (..=-0.1) => 7.0,
-        _ => 6.0,
-    };
+    let average_speed = cycling_speed(slope);
 
     let watts = calculate_watts(
         average_speed, // m/s
@@ -0,0 +1,138 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use petgraph::graph::NodeIndex;
+use petgraph::prelude::EdgeRef;
+
+use crate::calories::cycling_speed;
+use crate::graph::{Graph, Point};
+
+/// Cycling times from a point to every node reachable within a time limit
+pub struct Isochrone {
+    times: HashMap<NodeIndex, f64>,
+    previous: HashMap<NodeIndex, NodeIndex>,
+}
+
+/// Geometry of the ride from the start of an isochrone to one of its nodes
+#[derive(Debug, Clone)]
+pub struct BikeLeg {
+    /// [lon, lat] of every point along the ride
+    pub coordinates: Vec<[f64; 2]>,
+    /// Elevation in meters of each point in `coordinates`
+    pub elevations: Vec<f32>,
+    pub length_m: f64,
+}
+
+#[derive(PartialEq)]
+struct QueueItem {
+    secs: f64,
+    node: NodeIndex,
+}
+
+impl Eq for QueueItem {}
+
+impl PartialOrd for QueueItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the BinaryHeap pops the earliest node first
+        other.secs.total_cmp(&self.secs)
+    }
+}
+
+/// Seconds to ride an edge from `from` to `to`, slower uphill and faster downhill
+fn edge_secs(graph: &Graph, from: NodeIndex, to: NodeIndex, dist: f32) -> f64 {
+    let elevation_diff = (graph.graph[to].ele - graph.graph[from].ele) as f64;
+    let slope = elevation_diff / dist as f64;
+    dist as f64 / cycling_speed(slope)
+}
+
+/// Dijkstra over the bike network from the node closest to `start`, stopping after `max_secs`.
+/// Returns None if no node is within `max_snap_m` of `start`.
+pub fn isochrone(graph: &Graph, start: &Point, max_secs: f64, max_snap_m: f32) -> Option<Isochrone> {
+    let snap = graph.location_index.snap_closest(start);
+    if snap.dist > max_snap_m {
+        return None;
+    }
+    let start_node = NodeIndex::new(snap.point_snap.point_id);
+
+    let mut times = HashMap::new();
+    let mut previous = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    times.insert(start_node, 0.0);
+    queue.push(QueueItem { secs: 0.0, node: start_node });
+
+    while let Some(QueueItem { secs, node }) = queue.pop() {
+        if times.get(&node).is_some_and(|best| *best < secs) {
+            continue;
+        }
+
+        for edge in graph.graph.edges(node) {
+            if !edge.weight().access_friendly {
+                continue;
+            }
+            let next = if edge.source() == node { edge.target() } else { edge.source() };
+            let next_secs = secs + edge_secs(graph, node, next, edge.weight().dist);
+            if next_secs > max_secs || times.get(&next).is_some_and(|best| *best <= next_secs) {
+                continue;
+            }
+            times.insert(next, next_secs);
+            previous.insert(next, node);
+            queue.push(QueueItem { secs: next_secs, node: next });
+        }
+    }
+
+    Some(Isochrone { times, previous })
+}
+
+impl Isochrone {
+    /// Every reached node as (node id, position, cycling seconds)
+    pub fn reached<'a>(&'a self, graph: &'a Graph) -> impl Iterator<Item = (usize, Point, f64)> + 'a {
+        self.times
+            .iter()
+            .map(|(node, secs)| (node.index(), graph.graph[*node].point(), *secs))
+    }
+
+    /// The ride from the start to `node`, following the edge geometry
+    pub fn leg_to(&self, graph: &Graph, node: usize) -> Option<BikeLeg> {
+        let mut path = vec![NodeIndex::new(node)];
+        if !self.times.contains_key(&path[0]) {
+            return None;
+        }
+        while let Some(prev) = self.previous.get(path.last().unwrap()) {
+            path.push(*prev);
+        }
+        path.reverse();
+
+        let mut points: Vec<Point> = vec![graph.graph[path[0]].point()];
+        let mut length_m = 0.0;
+        for indices in path.windows(2) {
+            let (prev, cur) = (indices[0], indices[1]);
+            let edge = graph.graph.find_edge(prev, cur).unwrap();
+            let (source, _) = graph.graph.edge_endpoints(edge).unwrap();
+            let edge_weight = &graph.graph[edge];
+            length_m += edge_weight.dist as f64;
+
+            let mut edge_points = edge_weight.points(&graph.db).to_vec();
+            if source != prev {
+                edge_points.reverse();
+            }
+            edge_points.push(graph.graph[cur].point());
+            for point in edge_points {
+                if points.last() != Some(&point) {
+                    points.push(point);
+                }
+            }
+        }
+
+        Some(BikeLeg {
+            coordinates: points.iter().map(|p| [p.lon as f64, p.lat as f64]).collect(),
+            elevations: points.iter().map(|p| p.ele).collect(),
+            length_m,
+        })
+    }
+}
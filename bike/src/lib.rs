@@ -5,8 +5,10 @@ mod virtual_graph;
 mod graph;
 mod real_edge_weight;
 mod calories;
+mod isochrone;
 
 pub use parse_graph::*;
 pub use bicycle_rating::rate_bicycle_friendliness;
 pub use graph::*;
-pub use real_edge_weight::RouteOptions;
\ No newline at end of file
+pub use real_edge_weight::RouteOptions;
+pub use isochrone::{isochrone, BikeLeg, Isochrone};
\ No newline at end of file
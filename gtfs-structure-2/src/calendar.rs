@@ -4,15 +4,15 @@ use rustc_hash::FxHashMap;
 
 use chrono::{Datelike, NaiveDate, Weekday};
 
-use crate::gtfs_wrapper::{try_parse_id, vec_to_hashmap, FromWithAgencyId};
+use crate::gtfs_wrapper::{try_parse_id, vec_to_hashmap, with_agency_id, FromWithAgencyId};
 use crate::IdType;
 use gtfs_structures::CalendarDate;
 
-#[derive(Archive, Serialize, Deserialize, Debug)]
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[archive(check_bytes)]
 pub struct NaiveDate1(u32);
 
-#[derive(Archive, Serialize, Deserialize, Debug)]
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[archive(check_bytes)]
 pub struct Service {
     pub id: IdType,
@@ -50,6 +50,22 @@ impl FromWithAgencyId<gtfs_structures::Calendar> for Service {
 }
 
 impl Service {
+    /// Service running every day, for trips that aren't from a feed
+    pub fn every_day(id: IdType) -> Self {
+        Service {
+            id,
+            monday: true,
+            tuesday: true,
+            wednesday: true,
+            thursday: true,
+            friday: true,
+            saturday: true,
+            sunday: true,
+            start_date: NaiveDate1(0),
+            end_date: NaiveDate1(365),
+        }
+    }
+
     #[inline]
     pub fn runs_on_date(&self, date: NaiveDate) -> bool {
         match date.weekday() {
@@ -64,7 +80,7 @@ impl Service {
     }
 }
 
-#[derive(Serialize, Deserialize, Archive, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Archive, PartialEq, Eq, Debug, Clone)]
 #[archive(check_bytes)]
 pub enum Exception {
     Added,
@@ -80,7 +96,7 @@ impl From<gtfs_structures::Exception> for Exception {
     }
 }
 
-#[derive(Serialize, Deserialize, Archive, Debug)]
+#[derive(Serialize, Deserialize, Archive, Debug, Clone)]
 #[archive(check_bytes)]
 pub struct CalendarException {
     /// Identifier of the service that is modified at this date
@@ -104,7 +120,7 @@ impl FromWithAgencyId<gtfs_structures::CalendarDate> for CalendarException {
     }
 }
 
-#[derive(Debug, Archive, Serialize, Deserialize)]
+#[derive(Debug, Archive, Serialize, Deserialize, Clone)]
 #[archive(check_bytes)]
 pub struct CalendarExceptionList(FxHashMap<u32, CalendarException>);
 
@@ -118,7 +134,7 @@ impl CalendarExceptionList {
     }
 }
 
-#[derive(Debug, Default, Archive, Serialize, Deserialize)]
+#[derive(Debug, Default, Archive, Serialize, Deserialize, Clone)]
 #[archive(check_bytes)]
 pub struct Calendar {
     pub services: FxHashMap<IdType, Service>,
@@ -130,16 +146,33 @@ impl Calendar {
         self.services.extend(other.services);
         self.exceptions.extend(other.exceptions);
     }
-    pub fn runs_on_date(&self, service_id: IdType, date: NaiveDate) -> bool {
-        let normal = self.services.get(&service_id).map(|a| a.runs_on_date(date));
 
-        normal.or_else(|| {
-            self.exceptions
-                .get(&service_id)
-                .and_then(|a| a.runs_on_date(date))
-        });
+    /// Moves the services to another agency ID, see [crate::gtfs_wrapper::Gtfs1::set_agency_id]
+    pub fn set_agency_id(&mut self, agency_id: u16) {
+        for service in self.services.values_mut() {
+            service.id.0 = agency_id;
+        }
+        for exception in self.exceptions.values_mut().flat_map(|list| list.0.values_mut()) {
+            exception.service_id.0 = agency_id;
+        }
+        self.services = with_agency_id(std::mem::take(&mut self.services), agency_id);
+        self.exceptions = with_agency_id(std::mem::take(&mut self.exceptions), agency_id);
+    }
+    pub fn runs_on_date(&self, service_id: IdType, date: NaiveDate) -> bool {
+        // calendar_dates.txt exceptions (e.g. holidays) take precedence over the weekly pattern
+        let exception = self
+            .exceptions
+            .get(&service_id)
+            .and_then(|a| a.runs_on_date(date));
+        if let Some(runs) = exception {
+            return runs;
+        }
 
-        normal.unwrap_or(true)
+        match self.services.get(&service_id) {
+            Some(service) => service.runs_on_date(date),
+            // Services only defined through calendar_dates.txt run only on their listed dates
+            None => !self.exceptions.contains_key(&service_id),
+        }
     }
 
     pub fn parse(calendar: Vec<Service>, exceptions_list: Vec<CalendarException>) -> Self {
@@ -0,0 +1,170 @@
+use crate::gtfs_wrapper::{try_parse_id, with_agency_id, FromWithAgencyId};
+use crate::IdType;
+use rkyv::{Archive, Deserialize, Serialize};
+use rustc_hash::FxHashMap;
+
+/// A fare and the transfers it allows. See <https://gtfs.org/reference/static/#fare_attributestxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct FareAttribute {
+    pub id: IdType,
+    pub price: f64,
+    pub currency: String,
+    /// Transfers allowed on this fare. None if unlimited.
+    pub transfers: Option<u32>,
+    /// Seconds from the first boarding until transfers expire
+    pub transfer_duration: Option<u32>,
+}
+
+/// Which itineraries a [FareAttribute] applies to. Unset fields match anything.
+/// See <https://gtfs.org/reference/static/#fare_rulestxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct FareRule {
+    pub fare_id: IdType,
+    pub route_id: Option<IdType>,
+    pub origin_id: Option<IdType>,
+    pub destination_id: Option<IdType>,
+    pub contains_id: Option<IdType>,
+}
+
+/// Fares v2 product. See <https://gtfs.org/reference/static/#fare_productstxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct FareProduct {
+    pub id: IdType,
+    pub amount: f64,
+    pub currency: String,
+}
+
+/// Fares v2 price of a single leg. Unset fields match anything.
+/// See <https://gtfs.org/reference/static/#fare_leg_rulestxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
+pub struct FareLegRule {
+    pub network_id: Option<IdType>,
+    pub from_area_id: Option<IdType>,
+    pub to_area_id: Option<IdType>,
+    pub fare_product_id: IdType,
+}
+
+/// Fare data of one agency, in both the fare_attributes/fare_rules and the fares v2 formats
+#[derive(Archive, Serialize, Deserialize, Debug, Default, Clone)]
+#[archive(check_bytes)]
+pub struct Fares {
+    pub attributes: FxHashMap<IdType, FareAttribute>,
+    pub rules: Vec<FareRule>,
+    pub products: FxHashMap<IdType, FareProduct>,
+    pub leg_rules: Vec<FareLegRule>,
+    /// Areas of each stop. Areas given for a parent station are also listed under its platforms.
+    pub stop_areas: FxHashMap<IdType, Vec<IdType>>,
+}
+
+impl Fares {
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.leg_rules.is_empty()
+    }
+
+    /// Moves the fares to another agency ID, see [crate::gtfs_wrapper::Gtfs1::set_agency_id]
+    pub fn set_agency_id(&mut self, agency_id: u16) {
+        let set = |id: &mut IdType| id.0 = agency_id;
+        for attribute in self.attributes.values_mut() {
+            set(&mut attribute.id);
+        }
+        for rule in &mut self.rules {
+            set(&mut rule.fare_id);
+            let optional_ids = [
+                &mut rule.route_id,
+                &mut rule.origin_id,
+                &mut rule.destination_id,
+                &mut rule.contains_id,
+            ];
+            optional_ids.into_iter().flatten().for_each(set);
+        }
+        for product in self.products.values_mut() {
+            set(&mut product.id);
+        }
+        for rule in &mut self.leg_rules {
+            set(&mut rule.fare_product_id);
+            let optional_ids = [&mut rule.network_id, &mut rule.from_area_id, &mut rule.to_area_id];
+            optional_ids.into_iter().flatten().for_each(set);
+        }
+        self.stop_areas.values_mut().flatten().for_each(set);
+        self.attributes = with_agency_id(std::mem::take(&mut self.attributes), agency_id);
+        self.products = with_agency_id(std::mem::take(&mut self.products), agency_id);
+        self.stop_areas = with_agency_id(std::mem::take(&mut self.stop_areas), agency_id);
+    }
+}
+
+fn parse_amount(amount: &str) -> f64 {
+    amount.trim().parse().unwrap_or_else(|_| {
+        eprintln!("Invalid fare amount {amount}");
+        0.0
+    })
+}
+
+impl FromWithAgencyId<gtfs_structures::FareAttribute> for FareAttribute {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::FareAttribute) -> Self
+    where
+        Self: Sized,
+    {
+        let transfers = match f.transfers {
+            gtfs_structures::Transfers::Unlimited => None,
+            gtfs_structures::Transfers::NoTransfer => Some(0),
+            gtfs_structures::Transfers::UniqueTransfer => Some(1),
+            gtfs_structures::Transfers::TwoTransfers => Some(2),
+            gtfs_structures::Transfers::Other(count) => Some(count.max(0) as u32),
+        };
+        Self {
+            id: (agency_id, try_parse_id(&f.id)),
+            price: parse_amount(&f.price),
+            currency: f.currency,
+            transfers,
+            transfer_duration: f.transfer_duration.map(|secs| secs as u32),
+        }
+    }
+}
+
+impl FromWithAgencyId<gtfs_structures::RawFareRule> for FareRule {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawFareRule) -> Self
+    where
+        Self: Sized,
+    {
+        let id = |id: Option<String>| id.map(|id| (agency_id, try_parse_id(&id)));
+        Self {
+            fare_id: (agency_id, try_parse_id(&f.fare_id)),
+            route_id: id(f.route_id),
+            origin_id: id(f.origin_id),
+            destination_id: id(f.destination_id),
+            contains_id: id(f.contains_id),
+        }
+    }
+}
+
+impl FromWithAgencyId<gtfs_structures::RawFareProduct> for FareProduct {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawFareProduct) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            id: (agency_id, try_parse_id(&f.fare_product_id)),
+            amount: parse_amount(&f.amount),
+            currency: f.currency,
+        }
+    }
+}
+
+impl FromWithAgencyId<gtfs_structures::RawFareLegRule> for FareLegRule {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawFareLegRule) -> Self
+    where
+        Self: Sized,
+    {
+        let id = |id: Option<String>| id.map(|id| (agency_id, try_parse_id(&id)));
+        Self {
+            network_id: id(f.network_id),
+            from_area_id: id(f.from_area_id),
+            to_area_id: id(f.to_area_id),
+            fare_product_id: (agency_id, try_parse_id(&f.fare_product_id)),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 
 use crate::calendar::{Calendar, CalendarException, Service};
+use crate::fares::{FareAttribute, FareLegRule, FareProduct, FareRule, Fares};
 use crate::shape::Shape;
 use crate::IdType;
 use gtfs_structures::{Agency, CalendarDate, RawGtfs, RawStopTime, RawTrip};
@@ -20,7 +21,7 @@ pub type AgencyId = u16;
 
 static AGENCY_COUNT: AtomicU16 = AtomicU16::new(0);
 
-#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
 #[archive(check_bytes)]
 pub enum RouteType {
     /// Tram, Streetcar, Light rail. Any light rail or street level system within a metropolitan area
@@ -133,6 +134,164 @@ impl From<gtfs_structures::LocationType> for LocationType {
     }
 }
 
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[archive(check_bytes)]
+pub enum TransferType {
+    /// Recommended transfer point between routes
+    #[default]
+    Recommended,
+    /// Departing vehicle waits for arriving one
+    Timed,
+    /// Transfer requires a minimum amount of time between arrival and departure to ensure a connection.
+    MinTime,
+    /// Transfer is not possible at this location
+    Impossible,
+}
+
+impl From<gtfs_structures::TransferType> for TransferType {
+    fn from(value: gtfs_structures::TransferType) -> Self {
+        match value {
+            gtfs_structures::TransferType::Recommended => TransferType::Recommended,
+            gtfs_structures::TransferType::Timed => TransferType::Timed,
+            gtfs_structures::TransferType::MinTime => TransferType::MinTime,
+            gtfs_structures::TransferType::Impossible => TransferType::Impossible,
+        }
+    }
+}
+
+/// Transfer rule between two stops. See <https://gtfs.org/reference/static/#transferstxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[archive(check_bytes)]
+pub struct Transfer {
+    /// Stop from which to leave
+    pub from_stop_id: IdType,
+    /// Stop which to transfer to
+    pub to_stop_id: IdType,
+    /// Type of the transfer
+    pub transfer_type: TransferType,
+    /// Minimum time needed to make the transfer in seconds
+    pub min_transfer_time: Option<u32>,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[archive(check_bytes)]
+pub enum PathwayMode {
+    #[default]
+    Walkway,
+    Stairs,
+    MovingSidewalk,
+    Escalator,
+    Elevator,
+    /// Into an area where proof of payment is required
+    FareGate,
+    /// Out of an area where proof of payment is required
+    ExitGate,
+}
+
+impl From<gtfs_structures::PathwayMode> for PathwayMode {
+    fn from(value: gtfs_structures::PathwayMode) -> Self {
+        match value {
+            gtfs_structures::PathwayMode::Walkway => PathwayMode::Walkway,
+            gtfs_structures::PathwayMode::Stairs => PathwayMode::Stairs,
+            gtfs_structures::PathwayMode::MovingSidewalk => PathwayMode::MovingSidewalk,
+            gtfs_structures::PathwayMode::Escalator => PathwayMode::Escalator,
+            gtfs_structures::PathwayMode::Elevator => PathwayMode::Elevator,
+            gtfs_structures::PathwayMode::FareGate => PathwayMode::FareGate,
+            gtfs_structures::PathwayMode::ExitGate => PathwayMode::ExitGate,
+        }
+    }
+}
+
+/// Pathway linking two locations of a station. See <https://gtfs.org/reference/static/#pathwaystxt>
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
+#[archive(check_bytes)]
+pub struct Pathway {
+    /// Location at which the pathway begins
+    pub from_stop_id: IdType,
+    /// Location at which the pathway ends
+    pub to_stop_id: IdType,
+    /// Type of pathway between the specified (from_stop_id, to_stop_id) pair
+    pub mode: PathwayMode,
+    /// Whether the pathway can also be used from to_stop_id to from_stop_id
+    pub is_bidirectional: bool,
+    /// Horizontal length in meters
+    pub length: Option<f32>,
+    /// Average seconds needed to walk through the pathway
+    pub traversal_time: Option<u32>,
+}
+
+impl Pathway {
+    /// Whether a wheelchair can use this pathway
+    pub fn is_step_free(&self) -> bool {
+        !matches!(self.mode, PathwayMode::Stairs | PathwayMode::Escalator)
+    }
+
+    fn reversed(&self) -> Pathway {
+        Pathway {
+            from_stop_id: self.to_stop_id,
+            to_stop_id: self.from_stop_id,
+            ..*self
+        }
+    }
+}
+
+impl FromWithAgencyId<gtfs_structures::RawPathway> for Pathway {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawPathway) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            from_stop_id: (agency_id, try_parse_id(&f.from_stop_id)),
+            to_stop_id: (agency_id, try_parse_id(&f.to_stop_id)),
+            mode: f.mode.into(),
+            is_bidirectional: f.is_bidirectional == gtfs_structures::PathwayDirectionType::Bidirectional,
+            length: f.length,
+            traversal_time: f.traversal_time,
+        }
+    }
+}
+
+/// Headway-based service of a trip. See <https://gtfs.org/reference/static/#frequenciestxt>
+#[derive(Debug, Clone)]
+pub struct Frequency {
+    /// Template trip whose stop times are repeated
+    pub trip_id: IdType,
+    /// Time at which the first vehicle departs from the first stop of the trip
+    pub start_time: u32,
+    /// Time at which service changes to a different headway (or ceases) at the first stop in the trip
+    pub end_time: u32,
+    /// Time, in seconds, between departures from the same stop
+    pub headway_secs: u32,
+}
+
+impl FromWithAgencyId<gtfs_structures::RawFrequency> for Frequency {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawFrequency) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            trip_id: (agency_id, try_parse_id(&f.trip_id)),
+            start_time: f.start_time,
+            end_time: f.end_time,
+            headway_secs: f.headway_secs,
+        }
+    }
+}
+
+impl FromWithAgencyId<gtfs_structures::RawTransfer> for Transfer {
+    fn from_with_agency_id(agency_id: u16, f: gtfs_structures::RawTransfer) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            from_stop_id: (agency_id, try_parse_id(&f.from_stop_id)),
+            to_stop_id: (agency_id, try_parse_id(&f.to_stop_id)),
+            transfer_type: f.transfer_type.into(),
+            min_transfer_time: f.min_transfer_time,
+        }
+    }
+}
+
 pub trait FromWithAgencyId<From> {
     fn from_with_agency_id(agency_id: u16, f: From) -> Self
     where
@@ -164,9 +323,11 @@ pub fn try_parse_id(a: &str) -> u64 {
 #[archive(check_bytes)]
 pub struct StopTime {
     /// Arrival time of the stop time.
-    /// It's an option since the intermediate stops can have have no arrival
-    /// and this arrival needs to be interpolated
+    /// Untimed intermediate stops are interpolated when building [Gtfs1], so it's only None before then
     pub arrival_time: Option<u32>,
+    /// Departure time of the stop time. Differs from the arrival time when the vehicle dwells at the stop.
+    /// Falls back to the arrival time, so it's None exactly when the arrival time is
+    pub departure_time: Option<u32>,
     /// Order of stops for a particular trip. The values must increase along the trip but do not need to be consecutive
     pub stop_sequence: u16,
     /// Text that appears on signage identifying the trip's destination to riders
@@ -176,6 +337,8 @@ pub struct StopTime {
 
     pub index_of_stop_time: usize,
     pub shape_index: f32,
+    /// Distance along the shape from shapes.txt, in the feed's units
+    pub shape_dist_traveled: Option<f32>,
 }
 
 /// A physical stop, station or area. See <https://gtfs.org/reference/static/#stopstxt>
@@ -194,6 +357,15 @@ pub struct Stop {
     pub latitude: Option<f64>,
     pub location_type: LocationType,
     pub parent_station: Option<String>,
+    /// Fare zone. Platforms without one inherit it from their parent station.
+    pub zone_id: Option<IdType>,
+    /// Station this location is part of. Boarding areas belong to the station of their platform.
+    pub station_id: Option<IdType>,
+    /// Level of the location within its station, see [Gtfs1::levels]
+    pub level_id: Option<IdType>,
+    /// Whether wheelchair boardings are possible from this stop. Platforms without information
+    /// inherit it from their parent station, and platforms only reachable by stairs are NotAvailable.
+    pub wheelchair_boarding: Availability1,
     // pub shape_travelled_index: f64
 }
 
@@ -210,6 +382,10 @@ impl FromWithAgencyId<gtfs_structures::Stop> for Stop {
             latitude: f.latitude,
             location_type: f.location_type.into(),
             parent_station: f.parent_station,
+            zone_id: f.zone_id.map(|zone| (agency_id, try_parse_id(&zone))),
+            station_id: None,
+            level_id: f.level_id.map(|level| (agency_id, try_parse_id(&level))),
+            wheelchair_boarding: f.wheelchair_boarding.into(),
         }
     }
 }
@@ -228,6 +404,19 @@ pub enum Availability1 {
     Unknown(i32),
 }
 
+impl From<gtfs_structures::Availability> for Availability1 {
+    fn from(value: gtfs_structures::Availability) -> Self {
+        match value {
+            gtfs_structures::Availability::InformationNotAvailable => {
+                Availability1::InformationNotAvailable
+            }
+            gtfs_structures::Availability::Available => Availability1::Available,
+            gtfs_structures::Availability::NotAvailable => Availability1::NotAvailable,
+            gtfs_structures::Availability::Unknown(i32) => Availability1::Unknown(i32),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize, Archive)]
 #[archive(check_bytes)]
 pub struct Trip {
@@ -249,6 +438,8 @@ pub struct Trip {
     pub direction_id: Option<DirectionType>,
     /// Identifies the block to which the trip belongs. A block consists of a single trip or many sequential trips made using the same vehicle, defined by shared service days and block_id. A block_id can have trips with different service days, making distinct blocks
     pub block_id: Option<String>,
+    /// Indicates wheelchair accessibility
+    pub wheelchair_accessible: Availability1,
 }
 
 impl Trip {
@@ -272,7 +463,7 @@ impl Trip {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Archive)]
+#[derive(Debug, Serialize, Deserialize, Default, Archive, Clone)]
 #[archive(check_bytes)]
 pub struct Route {
     /// Unique technical (not for the traveller) identifier for the route
@@ -292,6 +483,8 @@ pub struct Route {
     pub order: Option<u32>,
     pub color: String,
     pub text_color: String,
+    /// Network for fares v2 leg rules
+    pub network_id: Option<IdType>,
 }
 
 impl FromWithAgencyId<gtfs_structures::Route> for Route {
@@ -307,6 +500,7 @@ impl FromWithAgencyId<gtfs_structures::Route> for Route {
             order: a.order,
             color: a.color.to_string(),
             text_color: a.text_color.to_string(),
+            network_id: a.network_id.map(|network| (agency_id, try_parse_id(&network))),
         }
     }
 }
@@ -323,6 +517,7 @@ impl FromWithAgencyId<RawTrip> for Trip {
             trip_short_name: a.trip_short_name,
             direction_id: a.direction_id.map(Into::into),
             block_id: a.block_id,
+            wheelchair_accessible: a.wheelchair_accessible.into(),
         }
     }
 }
@@ -339,11 +534,17 @@ pub struct Gtfs0 {
     pub stop_times: Vec<StopTime>,
     pub calendar: Vec<Service>,
     pub calendar_dates: Vec<CalendarException>,
+    pub transfers: Vec<Transfer>,
+    pub frequencies: Vec<Frequency>,
+    pub pathways: Vec<Pathway>,
+    /// Level index by level_id
+    pub levels: FxHashMap<IdType, f64>,
+    pub fares: Fares,
     pub agency_id: u16,
     pub agency: Agency,
 }
 
-#[derive(Archive, Serialize, Deserialize, Debug, Default)]
+#[derive(Archive, Serialize, Deserialize, Debug, Default, Clone)]
 #[archive(check_bytes)]
 pub struct Gtfs1 {
     /// All stop by `stop_id`. Stops are in an [Arc] because they are also referenced by each [StopTime]
@@ -357,12 +558,163 @@ pub struct Gtfs1 {
     pub generated_shapes: FxHashMap<IdType, Vec<Shape>>,
 
     pub calendar: Calendar,
+    /// Transfers touching each stop, listed under both their from_stop_id and to_stop_id.
+    /// Transfers between parent stations are expanded to their child stops.
+    pub transfers: FxHashMap<IdType, Vec<Transfer>>,
+    /// Pathways touching each location, listed under both their from_stop_id and to_stop_id.
+    /// Bidirectional pathways are listed in both directions.
+    pub pathways: FxHashMap<IdType, Vec<Pathway>>,
+    /// Entrances of each station that has pathways, where the street connects to its pathway graph
+    pub station_entrances: FxHashMap<IdType, Vec<IdType>>,
+    /// Relative position of each level, 0 being the ground level
+    pub levels: FxHashMap<IdType, f64>,
+    /// Fare data of each agency. Fares never apply across agencies.
+    pub fares: FxHashMap<AgencyId, Fares>,
+    /// Numeric IDs given to non-numeric stop, route and trip IDs of the feeds, see [Gtfs1::lookup_id]
+    pub gtfs_ids: FxHashMap<String, u64>,
     pub agency_id: u16,
     pub agency_city: String,
     pub agency_name: String,
 }
 
 impl Gtfs1 {
+    /// ID of a stop, route or trip from the ID it has in the feed of `agency_id`. Unlike [try_parse_id],
+    /// this works after loading the archive, so IDs given in requests can be resolved.
+    pub fn lookup_id(&self, agency_id: u16, id: &str) -> Option<IdType> {
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => *self.gtfs_ids.get(id)?,
+        };
+        Some((agency_id, id))
+    }
+
+    /// Transfer rules for leaving from `stop_id`
+    pub fn transfers_from(&self, stop_id: IdType) -> impl Iterator<Item = &Transfer> + '_ {
+        self.transfers
+            .get(&stop_id)
+            .into_iter()
+            .flatten()
+            .filter(move |t| t.from_stop_id == stop_id)
+    }
+
+    /// Transfer rules for arriving at `stop_id`
+    pub fn transfers_to(&self, stop_id: IdType) -> impl Iterator<Item = &Transfer> + '_ {
+        self.transfers
+            .get(&stop_id)
+            .into_iter()
+            .flatten()
+            .filter(move |t| t.to_stop_id == stop_id)
+    }
+
+    /// Pathways leaving `stop_id`
+    pub fn pathways_from(&self, stop_id: IdType) -> impl Iterator<Item = &Pathway> + '_ {
+        self.pathways
+            .get(&stop_id)
+            .into_iter()
+            .flatten()
+            .filter(move |p| p.from_stop_id == stop_id)
+    }
+
+    /// Pathways arriving at `stop_id`
+    pub fn pathways_to(&self, stop_id: IdType) -> impl Iterator<Item = &Pathway> + '_ {
+        self.pathways
+            .get(&stop_id)
+            .into_iter()
+            .flatten()
+            .filter(move |p| p.to_stop_id == stop_id)
+    }
+
+    /// Levels climbed (or descended, if negative) from one location to another. Zero if either level is unknown.
+    pub fn level_change(&self, from_stop_id: IdType, to_stop_id: IdType) -> f64 {
+        let level_index = |stop_id| {
+            let level_id = self.stops.get(&stop_id)?.level_id?;
+            self.levels.get(&level_id).copied()
+        };
+        match (level_index(from_stop_id), level_index(to_stop_id)) {
+            (Some(from), Some(to)) => to - from,
+            _ => 0.0,
+        }
+    }
+
+    // Replaces each template trip of frequencies.txt by one trip per run, so the search sees every
+    // departure. Frequency-based (inexact) headways are expanded the same way as schedule-based ones.
+    fn expand_frequencies(&mut self, frequencies: Vec<Frequency>) {
+        let mut by_trip: FxHashMap<IdType, Vec<Frequency>> = FxHashMap::default();
+        for frequency in frequencies {
+            by_trip
+                .entry(frequency.trip_id)
+                .or_default()
+                .push(frequency);
+        }
+
+        for (trip_id, frequencies) in by_trip {
+            let Some(template) = self.trips.remove(&trip_id) else {
+                continue;
+            };
+            let Some(template_start) = template.stop_times.first().and_then(|st| st.departure_time)
+            else {
+                continue;
+            };
+
+            for frequency in frequencies {
+                if frequency.headway_secs == 0 {
+                    continue;
+                }
+                for start in (frequency.start_time..frequency.end_time)
+                    .step_by(frequency.headway_secs as usize)
+                {
+                    let id = (trip_id.0, try_parse_id(&format!("{}@{}", trip_id.1, start)));
+                    // The first stop can be arrived at before the window starts, e.g. when it starts at
+                    // midnight. Those times can't be before the service day and are clamped to it.
+                    let offset = start as i64 - template_start as i64;
+                    let shift = |time: u32| (time as i64 + offset).max(0) as u32;
+                    let mut trip = template.clone();
+                    trip.id = id;
+                    for st in &mut trip.stop_times {
+                        st.trip_id = id;
+                        st.arrival_time = st.arrival_time.map(shift);
+                        st.departure_time = st.departure_time.map(shift);
+                    }
+                    self.trips.insert(id, trip);
+                }
+            }
+        }
+    }
+
+    // Gives untimed stops a time proportional to their distance between the timed stops around them.
+    // Distances come from shape_dist_traveled when the whole trip has it, or else from where each
+    // stop was placed along the shape. Trips without any time can't be used and are removed.
+    fn interpolate_missing_times(&mut self) {
+        let mut untimed_trips = Vec::new();
+        let mut interpolated = Vec::new();
+        for trip in self.trips.values() {
+            if trip.stop_times.iter().all(|st| st.arrival_time.is_some()) {
+                continue;
+            }
+            if trip.stop_times.iter().all(|st| st.arrival_time.is_none()) {
+                untimed_trips.push(trip.id);
+                continue;
+            }
+            let distances = stop_distances(trip, self.get_shape(trip));
+            let mut times: Vec<Option<u32>> = trip.stop_times.iter().map(|st| st.arrival_time).collect();
+            interpolate_times(&mut times, &distances);
+            interpolated.push((trip.id, times));
+        }
+
+        for (trip_id, times) in interpolated {
+            let trip = self.trips.get_mut(&trip_id).unwrap();
+            for (st, time) in trip.stop_times.iter_mut().zip(times) {
+                st.arrival_time = time;
+                st.departure_time = st.departure_time.or(time);
+            }
+        }
+
+        for trip_id in untimed_trips {
+            eprintln!("Removing trip {:?} without any stop time", trip_id);
+            self.trips.remove(&trip_id);
+        }
+    }
+
     fn generate_shapes(&mut self) {
         for trip in self.trips.values() {
             if trip.shape_id.is_none() {
@@ -420,7 +772,10 @@ impl From<Gtfs0WithCity> for Gtfs1 {
         let mut a = b.gtfs0;
         AGENCY_COUNT.fetch_add(1, Ordering::SeqCst);
 
-        let stops = vec_to_hashmap(a.stops, |stop| stop.id);
+        let mut stops = vec_to_hashmap(a.stops, |stop| stop.id);
+        resolve_wheelchair_boarding(&mut stops, a.agency_id, &a.pathways);
+        resolve_stations(&mut stops, a.agency_id);
+        resolve_fare_zones(&mut stops, a.agency_id, &mut a.fares);
         let shapes = convert_shapes(a.shapes);
         let mut trips: FxHashMap<IdType, Trip> = a
             .trips
@@ -437,6 +792,7 @@ impl From<Gtfs0WithCity> for Gtfs1 {
                         trip_short_name: a.trip_short_name,
                         direction_id: a.direction_id,
                         block_id: a.block_id,
+                        wheelchair_accessible: a.wheelchair_accessible,
                         stop_times: Default::default(),
                     },
                 )
@@ -453,12 +809,20 @@ impl From<Gtfs0WithCity> for Gtfs1 {
         }
 
         let calendar = Calendar::parse(a.calendar, a.calendar_dates);
+        let transfers = index_transfers(&stops, a.agency_id, a.transfers);
+        let (pathways, station_entrances) = index_pathways(&stops, a.agency_id, a.pathways);
         let mut self_ = Self {
             stops,
             shapes,
             routes: vec_to_hashmap(a.routes, |route| route.id),
             trips,
             calendar,
+            transfers,
+            pathways,
+            station_entrances,
+            levels: a.levels,
+            fares: FxHashMap::from_iter([(a.agency_id, a.fares)]),
+            gtfs_ids: Default::default(),
             agency_id: a.agency_id,
             agency_city: b.agency_city,
             agency_name: a.agency.name,
@@ -467,11 +831,271 @@ impl From<Gtfs0WithCity> for Gtfs1 {
 
         self_.generate_shapes();
         process_stop_times_with_shape_dist_travelled(&mut self_);
+        self_.interpolate_missing_times();
+        // After shapes, so runs share the template's shape
+        self_.expand_frequencies(a.frequencies);
+        self_.gtfs_ids = non_numeric_ids(&self_);
 
         self_
     }
 }
 
+// Entries of ID_MAP for the stops, routes and trips of `gtfs`. Must run on the thread that parsed the feed.
+fn non_numeric_ids(gtfs: &Gtfs1) -> FxHashMap<String, u64> {
+    let used: FxHashSet<u64> = gtfs
+        .stops
+        .keys()
+        .chain(gtfs.routes.keys())
+        .chain(gtfs.trips.keys())
+        .map(|id| id.1)
+        .collect();
+    ID_MAP.with(|idmap| {
+        idmap
+            .borrow()
+            .iter()
+            .filter(|(_, id)| used.contains(id))
+            .map(|(name, id)| (name.clone(), *id))
+            .collect()
+    })
+}
+
+fn resolve_wheelchair_boarding(
+    stops: &mut FxHashMap<IdType, Stop>,
+    agency_id: u16,
+    pathways: &[Pathway],
+) {
+    let inherited: Vec<(IdType, Availability1)> = stops
+        .values()
+        .filter(|stop| stop.wheelchair_boarding == Availability1::InformationNotAvailable)
+        .filter_map(|stop| {
+            let parent = stops.get(&(agency_id, try_parse_id(stop.parent_station.as_ref()?)))?;
+            Some((stop.id, parent.wheelchair_boarding))
+        })
+        .collect();
+    for (stop_id, availability) in inherited {
+        stops.get_mut(&stop_id).unwrap().wheelchair_boarding = availability;
+    }
+
+    // Locations with pathways, and whether any of them is step-free
+    let mut step_free: FxHashMap<IdType, bool> = FxHashMap::default();
+    for pathway in pathways {
+        for stop_id in [pathway.from_stop_id, pathway.to_stop_id] {
+            *step_free.entry(stop_id).or_default() |= pathway.is_step_free();
+        }
+    }
+    for (stop_id, step_free) in step_free {
+        if let (false, Some(stop)) = (step_free, stops.get_mut(&stop_id)) {
+            stop.wheelchair_boarding = Availability1::NotAvailable;
+        }
+    }
+}
+
+// Platforms inherit the fare zone and fare areas of their parent station
+fn resolve_fare_zones(stops: &mut FxHashMap<IdType, Stop>, agency_id: u16, fares: &mut Fares) {
+    let inherited: Vec<(IdType, IdType)> = stops
+        .values()
+        .filter_map(|stop| {
+            let parent = (agency_id, try_parse_id(stop.parent_station.as_ref()?));
+            Some((stop.id, parent))
+        })
+        .collect();
+    for (stop_id, parent) in inherited {
+        if let Some(zone_id) = stops.get(&parent).and_then(|parent| parent.zone_id) {
+            stops.get_mut(&stop_id).unwrap().zone_id.get_or_insert(zone_id);
+        }
+        if let Some(areas) = fares.stop_areas.get(&parent).cloned() {
+            let stop_areas = fares.stop_areas.entry(stop_id).or_default();
+            for area in areas {
+                if !stop_areas.contains(&area) {
+                    stop_areas.push(area);
+                }
+            }
+        }
+    }
+}
+
+// Stations of platforms, entrances and nodes are their parent. Boarding areas are in the station of their platform.
+fn resolve_stations(stops: &mut FxHashMap<IdType, Stop>, agency_id: u16) {
+    let parent_of = |stop: &Stop| {
+        let parent = stops.get(&(agency_id, try_parse_id(stop.parent_station.as_ref()?)))?;
+        Some(parent)
+    };
+    let stations: Vec<(IdType, IdType)> = stops
+        .values()
+        .filter_map(|stop| {
+            let parent = parent_of(stop)?;
+            let station = match parent.location_type {
+                LocationType::StopArea => parent,
+                _ => parent_of(parent)?,
+            };
+            Some((stop.id, station.id))
+        })
+        .collect();
+    for (stop_id, station_id) in stations {
+        stops.get_mut(&stop_id).unwrap().station_id = Some(station_id);
+    }
+}
+
+// Boarding areas are joined to their platform, which is where trips stop
+fn index_pathways(
+    stops: &FxHashMap<IdType, Stop>,
+    agency_id: u16,
+    pathways: Vec<Pathway>,
+) -> (FxHashMap<IdType, Vec<Pathway>>, FxHashMap<IdType, Vec<IdType>>) {
+    let mut answer: FxHashMap<IdType, Vec<Pathway>> = FxHashMap::default();
+    let mut add = |pathway: Pathway| {
+        answer.entry(pathway.from_stop_id).or_default().push(pathway);
+        answer.entry(pathway.to_stop_id).or_default().push(pathway);
+    };
+
+    let mut stations_with_pathways = FxHashSet::default();
+    for pathway in pathways {
+        if !stops.contains_key(&pathway.from_stop_id) || !stops.contains_key(&pathway.to_stop_id) {
+            continue;
+        }
+        stations_with_pathways.extend(stops[&pathway.from_stop_id].station_id);
+        if pathway.is_bidirectional {
+            add(pathway.reversed());
+        }
+        add(pathway);
+    }
+
+    for stop in stops.values() {
+        if stop.location_type != LocationType::BoardingArea {
+            continue;
+        }
+        let Some(platform) = stop.parent_station.as_ref() else {
+            continue;
+        };
+        let link = Pathway {
+            from_stop_id: stop.id,
+            to_stop_id: (agency_id, try_parse_id(platform)),
+            mode: PathwayMode::Walkway,
+            is_bidirectional: true,
+            length: None,
+            traversal_time: Some(0),
+        };
+        add(link.reversed());
+        add(link);
+    }
+
+    let mut station_entrances: FxHashMap<IdType, Vec<IdType>> = FxHashMap::default();
+    for stop in stops.values() {
+        if stop.location_type != LocationType::StationEntrance || stop.latitude.is_none() {
+            continue;
+        }
+        if let Some(station_id) = stop.station_id.filter(|id| stations_with_pathways.contains(id)) {
+            station_entrances.entry(station_id).or_default().push(stop.id);
+        }
+    }
+    (answer, station_entrances)
+}
+
+fn index_transfers(
+    stops: &FxHashMap<IdType, Stop>,
+    agency_id: u16,
+    transfers: Vec<Transfer>,
+) -> FxHashMap<IdType, Vec<Transfer>> {
+    // Transfers are often given between parent stations, while trips stop at their platforms
+    let mut children: FxHashMap<IdType, Vec<IdType>> = FxHashMap::default();
+    for stop in stops.values() {
+        if let Some(parent) = &stop.parent_station {
+            children
+                .entry((agency_id, try_parse_id(parent)))
+                .or_default()
+                .push(stop.id);
+        }
+    }
+    let expand = |id: IdType| -> Vec<IdType> {
+        let mut ids = children.get(&id).cloned().unwrap_or_default();
+        if stops.contains_key(&id) {
+            ids.push(id);
+        }
+        ids
+    };
+
+    let mut answer: FxHashMap<IdType, Vec<Transfer>> = FxHashMap::default();
+    for transfer in transfers {
+        for from_stop_id in expand(transfer.from_stop_id) {
+            for to_stop_id in expand(transfer.to_stop_id) {
+                let expanded = Transfer {
+                    from_stop_id,
+                    to_stop_id,
+                    ..transfer
+                };
+                answer.entry(from_stop_id).or_default().push(expanded);
+                if from_stop_id != to_stop_id {
+                    answer.entry(to_stop_id).or_default().push(expanded);
+                }
+            }
+        }
+    }
+    answer
+}
+
+// Distance along the trip of each of its stop times
+fn stop_distances(trip: &Trip, shape: &[Shape]) -> Vec<f64> {
+    if let Some(distances) = trip
+        .stop_times
+        .iter()
+        .map(|st| st.shape_dist_traveled.map(f64::from))
+        .collect::<Option<Vec<f64>>>()
+    {
+        return distances;
+    }
+
+    // Meters along the shape up to each of its points. Degrees are close enough to planar at this scale.
+    let mut along_shape = vec![0.0];
+    for points in shape.windows(2) {
+        let dx = (points[1].longitude - points[0].longitude)
+            * points[0].latitude.to_radians().cos()
+            * 111_320.0;
+        let dy = (points[1].latitude - points[0].latitude) * 110_540.0;
+        along_shape.push(along_shape.last().unwrap() + (dx * dx + dy * dy).sqrt());
+    }
+    trip.stop_times
+        .iter()
+        .map(|st| {
+            let index = (st.shape_index.max(0.0) as usize).min(along_shape.len() - 1);
+            let next = (index + 1).min(along_shape.len() - 1);
+            let fraction = st.shape_index as f64 - index as f64;
+            along_shape[index] + (along_shape[next] - along_shape[index]) * fraction.clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+// Fills the None times between two known times. Falls back to spacing stops evenly when distances
+// don't increase between them, like on loops. Stops before the first or after the last known time
+// take that time.
+fn interpolate_times(times: &mut [Option<u32>], distances: &[f64]) {
+    let known: Vec<usize> = (0..times.len()).filter(|i| times[*i].is_some()).collect();
+    let (Some(&first), Some(&last)) = (known.first(), known.last()) else {
+        return;
+    };
+
+    for pair in known.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let (start_time, end_time) = (times[start].unwrap() as f64, times[end].unwrap() as f64);
+        let (start_distance, end_distance) = (distances[start], distances[end]);
+        let increasing = (start + 1..end)
+            .all(|i| distances[i] >= start_distance && distances[i] <= end_distance)
+            && end_distance > start_distance;
+
+        for i in start + 1..end {
+            let fraction = if increasing {
+                (distances[i] - start_distance) / (end_distance - start_distance)
+            } else {
+                (i - start) as f64 / (end - start) as f64
+            };
+            times[i] = Some((start_time + (end_time - start_time) * fraction).round() as u32);
+        }
+    }
+
+    let (first_time, last_time) = (times[first], times[last]);
+    times[..first].fill(first_time);
+    times[last + 1..].fill(last_time);
+}
+
 fn process_stop_times_with_shape_dist_travelled(gtfs: &mut Gtfs1) {
     let geo_shape = generate_rtree_for_shapes(&gtfs.shapes);
     let geo_shape_generated = generate_rtree_for_shapes(&gtfs.generated_shapes);
@@ -574,7 +1198,7 @@ pub fn split_by_agency(mut gtfs: LibraryGTFS) -> Vec<LibraryGTFS> {
             agency.id = Some(agency.name.clone());
         }
     }
-    'a: for agency in gtfs.agencies.as_ref().unwrap() {
+    for agency in gtfs.agencies.as_ref().unwrap() {
         let agency_id = agency.id.clone().unwrap();
 
         println!("Found agency: {}", agency.name);
@@ -582,18 +1206,20 @@ pub fn split_by_agency(mut gtfs: LibraryGTFS) -> Vec<LibraryGTFS> {
         // We care about shapes, calendar, calendar_dates, stops, routes, trips, stop_times
         // routes -> trips -> calendar -> calendar_dates -> shape -> stop_times -> stops
 
-        let (routes, trips, calendar, calendar_dates, shape, stop_times, stops) =
-            extract_objects_by_agency(&gtfs, &agency_id);
-
-        for st in &stop_times {
-            if st.arrival_time.is_none() {
-                eprintln!(
-                    "Missing arrival time for stop time {:?} agency {}",
-                    st, agency.name
-                );
-                continue 'a;
-            }
-        }
+        let (
+            routes,
+            trips,
+            calendar,
+            calendar_dates,
+            shape,
+            stop_times,
+            stops,
+            transfers,
+            frequencies,
+            pathways,
+        ) = extract_objects_by_agency(&gtfs, &agency_id);
+        let (fare_attributes, fare_rules, fare_products, fare_leg_rules, stop_areas) =
+            extract_fares_by_agency(&gtfs, &agency_id, &routes, &stops);
 
         let raw = LibraryGTFS {
             read_duration: gtfs.read_duration,
@@ -604,10 +1230,15 @@ pub fn split_by_agency(mut gtfs: LibraryGTFS) -> Vec<LibraryGTFS> {
             trips: Ok(trips),
             agencies: Ok(vec![agency.clone()]),
             shapes: Some(Ok(shape)),
-            fare_attributes: None,
-            frequencies: None,
-            transfers: None,
-            pathways: None,
+            fare_attributes: Some(Ok(fare_attributes)),
+            fare_rules: Some(Ok(fare_rules)),
+            fare_products: Some(Ok(fare_products)),
+            fare_leg_rules: Some(Ok(fare_leg_rules)),
+            stop_areas: Some(Ok(stop_areas)),
+            frequencies: Some(Ok(frequencies)),
+            transfers: Some(Ok(transfers)),
+            pathways: Some(Ok(pathways)),
+            levels: Some(Ok(unwrap_or_default(&gtfs.levels).into_owned())),
             feed_info: None,
             stop_times: Ok(stop_times),
             files: vec![],
@@ -641,6 +1272,9 @@ fn extract_objects_by_agency(
     Vec<gtfs_structures::Shape>,
     Vec<gtfs_structures::RawStopTime>,
     Vec<gtfs_structures::Stop>,
+    Vec<gtfs_structures::RawTransfer>,
+    Vec<gtfs_structures::RawFrequency>,
+    Vec<gtfs_structures::RawPathway>,
 ) {
     // Nasty code to extract all the objects depending on agency_id
     let routes = gtfs
@@ -692,7 +1326,31 @@ fn extract_objects_by_agency(
         .cloned()
         .collect::<Vec<_>>();
 
-    let stop_id_hash = build_hashset(&stop_times, |x| x.stop_id.clone());
+    let frequencies = unwrap_or_default(&gtfs.frequencies)
+        .iter()
+        .filter(|x| trips_id_hash.contains(&x.trip_id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut stop_id_hash = build_hashset(&stop_times, |x| x.stop_id.clone());
+    // Keep parent stations, which transfers can refer to
+    for stop in gtfs.stops.as_ref().unwrap() {
+        if stop_id_hash.contains(&stop.id) {
+            if let Some(parent) = &stop.parent_station {
+                stop_id_hash.insert(parent.clone());
+            }
+        }
+    }
+    // And the entrances, nodes and boarding areas of the kept stations, which pathways link
+    let station_locations = gtfs
+        .stops
+        .as_ref()
+        .unwrap()
+        .iter()
+        .filter(|x| x.parent_station.as_ref().is_some_and(|p| stop_id_hash.contains(p)))
+        .map(|x| x.id.clone())
+        .collect::<Vec<_>>();
+    stop_id_hash.extend(station_locations);
     let stops = gtfs
         .stops
         .as_ref()
@@ -701,6 +1359,19 @@ fn extract_objects_by_agency(
         .filter(|x| stop_id_hash.contains(&x.id))
         .cloned()
         .collect::<Vec<_>>();
+
+    // Transfers between agencies can't be represented once split, as stop IDs are per agency
+    let transfers = unwrap_or_default(&gtfs.transfers)
+        .iter()
+        .filter(|x| stop_id_hash.contains(&x.from_stop_id) && stop_id_hash.contains(&x.to_stop_id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let pathways = unwrap_or_default(&gtfs.pathways)
+        .iter()
+        .filter(|x| stop_id_hash.contains(&x.from_stop_id) || stop_id_hash.contains(&x.to_stop_id))
+        .cloned()
+        .collect::<Vec<_>>();
     (
         routes,
         trips,
@@ -709,14 +1380,133 @@ fn extract_objects_by_agency(
         shape,
         stop_times,
         stops,
+        transfers,
+        frequencies,
+        pathways,
     )
 }
 
+// Fare attributes name their agency, while fares v2 are tied to routes through their networks
+fn extract_fares_by_agency(
+    gtfs: &LibraryGTFS,
+    agency_id: &str,
+    routes: &[gtfs_structures::Route],
+    stops: &[gtfs_structures::Stop],
+) -> (
+    Vec<gtfs_structures::FareAttribute>,
+    Vec<gtfs_structures::RawFareRule>,
+    Vec<gtfs_structures::RawFareProduct>,
+    Vec<gtfs_structures::RawFareLegRule>,
+    Vec<gtfs_structures::RawStopArea>,
+) {
+    let routes_hash = build_hashset(routes, |x| x.id.clone());
+    let fare_rules = unwrap_or_default(&gtfs.fare_rules)
+        .iter()
+        .filter(|x| x.route_id.as_ref().map_or(true, |id| routes_hash.contains(id)))
+        .cloned()
+        .collect::<Vec<_>>();
+    let fare_rule_ids = build_hashset(&fare_rules, |x| x.fare_id.clone());
+    let fare_attributes = unwrap_or_default(&gtfs.fare_attributes)
+        .iter()
+        .filter(|x| match &x.agency_id {
+            Some(id) => id == agency_id,
+            None => fare_rule_ids.contains(&x.id),
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let networks_hash = build_hashset(routes, |x| x.network_id.clone());
+    let fare_leg_rules = unwrap_or_default(&gtfs.fare_leg_rules)
+        .iter()
+        .filter(|x| x.network_id.is_none() || networks_hash.contains(&x.network_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    let fare_product_ids = build_hashset(&fare_leg_rules, |x| x.fare_product_id.clone());
+    let fare_products = unwrap_or_default(&gtfs.fare_products)
+        .iter()
+        .filter(|x| fare_product_ids.contains(&x.fare_product_id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let stops_hash = build_hashset(stops, |x| x.id.clone());
+    let stop_areas = unwrap_or_default(&gtfs.stop_areas)
+        .iter()
+        .filter(|x| stops_hash.contains(&x.stop_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    (
+        fare_attributes,
+        fare_rules,
+        fare_products,
+        fare_leg_rules,
+        stop_areas,
+    )
+}
+
+fn fares_from_library(agency_id: u16, a: &mut LibraryGTFS) -> Fares {
+    let mut fares = Fares {
+        attributes: vec_to_hashmap(
+            a.fare_attributes
+                .take()
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| FareAttribute::from_with_agency_id(agency_id, a))
+                .collect(),
+            |fare| fare.id,
+        ),
+        rules: a
+            .fare_rules
+            .take()
+            .unwrap_or(Ok(vec![]))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| FareRule::from_with_agency_id(agency_id, a))
+            .collect(),
+        products: vec_to_hashmap(
+            a.fare_products
+                .take()
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| FareProduct::from_with_agency_id(agency_id, a))
+                .collect(),
+            |product| product.id,
+        ),
+        leg_rules: a
+            .fare_leg_rules
+            .take()
+            .unwrap_or(Ok(vec![]))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| FareLegRule::from_with_agency_id(agency_id, a))
+            .collect(),
+        stop_areas: Default::default(),
+    };
+    for stop_area in a.stop_areas.take().unwrap_or(Ok(vec![])).unwrap_or_default() {
+        fares
+            .stop_areas
+            .entry((agency_id, try_parse_id(&stop_area.stop_id)))
+            .or_default()
+            .push((agency_id, try_parse_id(&stop_area.area_id)));
+    }
+    fares
+}
+
 impl From<LibraryGTFS> for Gtfs0 {
-    fn from(a: LibraryGTFS) -> Self {
+    fn from(mut a: LibraryGTFS) -> Self {
         let agency_id = AGENCY_COUNT.fetch_add(1, Ordering::SeqCst);
         assert_eq!(a.agencies.as_ref().unwrap().len(), 1);
         Self {
+            fares: fares_from_library(agency_id, &mut a),
+            levels: a
+                .levels
+                .take()
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|level| ((agency_id, try_parse_id(&level.level_id)), level.level_index))
+                .collect(),
             agency: a.agencies.unwrap()[0].clone(),
             shapes: a
                 .shapes
@@ -739,6 +1529,27 @@ impl From<LibraryGTFS> for Gtfs0 {
                 .into_iter()
                 .map(|a| CalendarException::from_with_agency_id(agency_id, a))
                 .collect(),
+            transfers: a
+                .transfers
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| Transfer::from_with_agency_id(agency_id, a))
+                .collect(),
+            frequencies: a
+                .frequencies
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| Frequency::from_with_agency_id(agency_id, a))
+                .collect(),
+            pathways: a
+                .pathways
+                .unwrap_or(Ok(vec![]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| Pathway::from_with_agency_id(agency_id, a))
+                .collect(),
             stops: a
                 .stops
                 .unwrap()
@@ -762,12 +1573,15 @@ impl From<LibraryGTFS> for Gtfs0 {
                 .unwrap()
                 .into_iter()
                 .map(|st| StopTime {
-                    arrival_time: st.arrival_time,
+                    // Timepoints may only give a departure time
+                    arrival_time: st.arrival_time.or(st.departure_time),
+                    departure_time: st.departure_time.or(st.arrival_time),
                     stop_sequence: st.stop_sequence,
                     stop_id: (agency_id, try_parse_id(&st.stop_id)),
                     trip_id: (agency_id, try_parse_id(&st.trip_id)),
                     index_of_stop_time: 0,
                     shape_index: -1.0,
+                    shape_dist_traveled: st.shape_dist_traveled,
                 })
                 .collect(),
             agency_id,
@@ -791,6 +1605,18 @@ impl Gtfs1 {
 
         self.generated_shapes.extend(other.generated_shapes);
 
+        self.transfers.extend(other.transfers);
+
+        self.pathways.extend(other.pathways);
+
+        self.station_entrances.extend(other.station_entrances);
+
+        self.levels.extend(other.levels);
+
+        self.fares.extend(other.fares);
+
+        self.gtfs_ids.extend(other.gtfs_ids);
+
         Gtfs1 {
             stops: self.stops,
             routes: self.routes,
@@ -798,9 +1624,219 @@ impl Gtfs1 {
             shapes: self.shapes,
             generated_shapes: self.generated_shapes,
             calendar: self.calendar,
+            transfers: self.transfers,
+            pathways: self.pathways,
+            station_entrances: self.station_entrances,
+            levels: self.levels,
+            fares: self.fares,
+            gtfs_ids: self.gtfs_ids,
             agency_id: self.agency_id,
             agency_city: self.agency_city,
             agency_name: self.agency_name,
         }
     }
+
+    /// Removes everything of an agency merged in, so another version of its feed can be merged instead
+    pub fn remove_agency(&mut self, agency_id: AgencyId) {
+        let other_agency = |id: &IdType| id.0 != agency_id;
+        self.stops.retain(|id, _| other_agency(id));
+        self.routes.retain(|id, _| other_agency(id));
+        self.trips.retain(|id, _| other_agency(id));
+        self.shapes.retain(|id, _| other_agency(id));
+        self.generated_shapes.retain(|id, _| other_agency(id));
+        self.calendar.services.retain(|id, _| other_agency(id));
+        self.calendar.exceptions.retain(|id, _| other_agency(id));
+        self.transfers.retain(|id, _| other_agency(id));
+        self.pathways.retain(|id, _| other_agency(id));
+        self.station_entrances.retain(|id, _| other_agency(id));
+        self.levels.retain(|id, _| other_agency(id));
+        self.fares.remove(&agency_id);
+    }
+
+    /// Moves everything of a single agency's feed to another agency ID, e.g. so another version
+    /// of a feed doesn't share its ID with agencies it's merged with
+    pub fn set_agency_id(&mut self, agency_id: AgencyId) {
+        let set = |id: &mut IdType| id.0 = agency_id;
+
+        for stop in self.stops.values_mut() {
+            set(&mut stop.id);
+            let optional_ids = [&mut stop.zone_id, &mut stop.station_id, &mut stop.level_id];
+            optional_ids.into_iter().flatten().for_each(set);
+        }
+        for route in self.routes.values_mut() {
+            set(&mut route.id);
+            route.network_id.iter_mut().for_each(set);
+        }
+        for trip in self.trips.values_mut() {
+            set(&mut trip.id);
+            set(&mut trip.service_id);
+            set(&mut trip.route_id);
+            trip.shape_id.iter_mut().for_each(set);
+            for st in &mut trip.stop_times {
+                set(&mut st.stop_id);
+                set(&mut st.trip_id);
+            }
+        }
+        for shape in self.shapes.values_mut().chain(self.generated_shapes.values_mut()).flatten() {
+            set(&mut shape.id);
+        }
+        for transfer in self.transfers.values_mut().flatten() {
+            set(&mut transfer.from_stop_id);
+            set(&mut transfer.to_stop_id);
+        }
+        for pathway in self.pathways.values_mut().flatten() {
+            set(&mut pathway.from_stop_id);
+            set(&mut pathway.to_stop_id);
+        }
+        self.station_entrances.values_mut().flatten().for_each(set);
+        self.calendar.set_agency_id(agency_id);
+
+        self.stops = with_agency_id(std::mem::take(&mut self.stops), agency_id);
+        self.routes = with_agency_id(std::mem::take(&mut self.routes), agency_id);
+        self.trips = with_agency_id(std::mem::take(&mut self.trips), agency_id);
+        self.shapes = with_agency_id(std::mem::take(&mut self.shapes), agency_id);
+        self.generated_shapes = with_agency_id(std::mem::take(&mut self.generated_shapes), agency_id);
+        self.transfers = with_agency_id(std::mem::take(&mut self.transfers), agency_id);
+        self.pathways = with_agency_id(std::mem::take(&mut self.pathways), agency_id);
+        self.station_entrances = with_agency_id(std::mem::take(&mut self.station_entrances), agency_id);
+        self.levels = with_agency_id(std::mem::take(&mut self.levels), agency_id);
+        self.fares = std::mem::take(&mut self.fares)
+            .into_values()
+            .map(|mut fares| {
+                fares.set_agency_id(agency_id);
+                (agency_id, fares)
+            })
+            .collect();
+        self.agency_id = agency_id;
+    }
+}
+
+// The entries of `map` under the same IDs of another agency
+pub(crate) fn with_agency_id<V>(map: FxHashMap<IdType, V>, agency_id: AgencyId) -> FxHashMap<IdType, V> {
+    map.into_iter()
+        .map(|((_, id), value)| ((agency_id, id), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_gtfs::{test_trip, TestGtfs};
+
+    // Calls at stops 0, 1, 2... following shape (0, id)
+    fn trip_with_times(id: u64, times: &[Option<u32>], shape_dist_traveled: &[Option<f32>]) -> Trip {
+        let calls: Vec<(u64, Option<u32>)> = times
+            .iter()
+            .enumerate()
+            .map(|(stop, time)| (stop as u64, *time))
+            .collect();
+        let mut trip = test_trip(id, 0, &calls);
+        trip.shape_id = Some((0, id));
+        for (st, distance) in trip.stop_times.iter_mut().zip(shape_dist_traveled) {
+            st.shape_dist_traveled = *distance;
+        }
+        trip
+    }
+
+    #[test]
+    fn test_interpolate_shape_dist_traveled() {
+        let times = [Some(0), None, None, Some(1000)];
+        let trip = trip_with_times(1, &times, &[Some(0.0), Some(100.0), Some(400.0), Some(1000.0)]);
+        let distances = stop_distances(&trip, &[]);
+        assert_eq!(distances, vec![0.0, 100.0, 400.0, 1000.0]);
+
+        let mut times = times.to_vec();
+        interpolate_times(&mut times, &distances);
+        assert_eq!(times, vec![Some(0), Some(100), Some(400), Some(1000)]);
+    }
+
+    #[test]
+    fn test_interpolate_shape_index() {
+        // One stop lacks shape_dist_traveled, so stops are placed by where they are along the shape
+        let mut trip = trip_with_times(1, &[Some(0), None, Some(200)], &[Some(0.0), None, Some(2.0)]);
+        trip.stop_times[1].shape_index = 0.5;
+        let shape: Vec<Shape> = (0..3)
+            .map(|i| Shape {
+                longitude: i as f64 * 0.001,
+                sequence: i,
+                ..Default::default()
+            })
+            .collect();
+        let distances = stop_distances(&trip, &shape);
+        // 0.001 degrees of longitude are about 111 m at the equator
+        assert!((distances[1] - 55.66).abs() < 0.01);
+        assert!((distances[2] - 222.64).abs() < 0.01);
+
+        let mut times = vec![Some(0), None, Some(200)];
+        interpolate_times(&mut times, &distances);
+        assert_eq!(times, vec![Some(0), Some(50), Some(200)]);
+    }
+
+    #[test]
+    fn test_interpolate_leading_and_trailing() {
+        let mut times = vec![None, Some(100), None, Some(300), None];
+        interpolate_times(&mut times, &[0.0, 100.0, 200.0, 300.0, 400.0]);
+        assert_eq!(times, vec![Some(100), Some(100), Some(200), Some(300), Some(300)]);
+    }
+
+    #[test]
+    fn test_interpolate_loop() {
+        // The trip ends where it started, so stops are spaced evenly instead
+        let mut times = vec![Some(0), None, None, Some(300)];
+        interpolate_times(&mut times, &[0.0, 500.0, 1000.0, 0.0]);
+        assert_eq!(times, vec![Some(0), Some(100), Some(200), Some(300)]);
+
+        // Going back along the way between the timed stops
+        let mut times = vec![Some(0), None, None, Some(300)];
+        interpolate_times(&mut times, &[0.0, 800.0, 1200.0, 900.0]);
+        assert_eq!(times, vec![Some(0), Some(100), Some(200), Some(300)]);
+    }
+
+    #[test]
+    fn test_interpolate_missing_times() {
+        let partial = trip_with_times(1, &[Some(0), None, Some(100)], &[Some(0.0), Some(30.0), Some(100.0)]);
+        let untimed = trip_with_times(2, &[None, None], &[None, None]);
+        let mut gtfs = TestGtfs::default().trip(partial).trip(untimed).build();
+        gtfs.shapes.insert((0, 1), Vec::new());
+
+        gtfs.interpolate_missing_times();
+        assert!(!gtfs.trips.contains_key(&(0, 2)));
+        let stop_time = &gtfs.trips[&(0, 1)].stop_times[1];
+        assert_eq!(stop_time.arrival_time, Some(30));
+        assert_eq!(stop_time.departure_time, Some(30));
+    }
+
+    #[test]
+    fn test_expand_frequencies_from_midnight() {
+        // The vehicle waits 2 minutes at the first stop, in a window starting at midnight
+        let mut template = test_trip(1, 1, &[(1, Some(3600)), (2, Some(4200))]);
+        template.stop_times[0].departure_time = Some(3720);
+        let mut gtfs = TestGtfs::default().trip(template).build();
+        let frequency = Frequency {
+            trip_id: (0, 1),
+            start_time: 0,
+            end_time: 1200,
+            headway_secs: 600,
+        };
+        gtfs.expand_frequencies(vec![frequency]);
+
+        let mut times: Vec<Vec<(Option<u32>, Option<u32>)>> = gtfs
+            .trips
+            .values()
+            .map(|trip| {
+                trip.stop_times
+                    .iter()
+                    .map(|st| (st.arrival_time, st.departure_time))
+                    .collect()
+            })
+            .collect();
+        times.sort();
+        assert_eq!(
+            times,
+            vec![
+                vec![(Some(0), Some(0)), (Some(480), Some(480))],
+                vec![(Some(480), Some(600)), (Some(1080), Some(1080))],
+            ]
+        );
+    }
 }
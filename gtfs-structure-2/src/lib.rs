@@ -2,5 +2,8 @@
 pub type IdType = (u16, u64);
 
 pub mod calendar;
+pub mod fares;
 pub mod gtfs_wrapper;
 pub mod shape;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_gtfs;
@@ -0,0 +1,55 @@
+//! Small schedules for tests. Everything belongs to agency 0, and only has the fields tests set.
+
+use crate::gtfs_wrapper::{Gtfs1, Route, Stop, StopTime, Trip};
+
+/// Trip `(0, id)` of route `(0, route_id)`, calling at stops `(0, stop)` at their times and leaving
+/// as soon as it arrives. Stop sequences and shape indices are the positions of the calls.
+pub fn test_trip(id: u64, route_id: u64, calls: &[(u64, Option<u32>)]) -> Trip {
+    let stop_times = calls
+        .iter()
+        .enumerate()
+        .map(|(index, (stop, time))| StopTime {
+            arrival_time: *time,
+            departure_time: *time,
+            stop_sequence: index as u16,
+            stop_id: (0, *stop),
+            trip_id: (0, id),
+            index_of_stop_time: index,
+            shape_index: index as f32,
+            shape_dist_traveled: None,
+        })
+        .collect();
+    Trip {
+        id: (0, id),
+        route_id: (0, route_id),
+        stop_times,
+        ..Default::default()
+    }
+}
+
+/// Builds a [Gtfs1] for tests
+#[derive(Default)]
+pub struct TestGtfs(Gtfs1);
+
+impl TestGtfs {
+    /// Adds stop `(0, id)`
+    pub fn stop(mut self, id: u64, stop: Stop) -> Self {
+        self.0.stops.insert((0, id), Stop { id: (0, id), ..stop });
+        self
+    }
+
+    /// Adds route `(0, id)`
+    pub fn route(mut self, id: u64, route: Route) -> Self {
+        self.0.routes.insert((0, id), Route { id: (0, id), ..route });
+        self
+    }
+
+    pub fn trip(mut self, trip: Trip) -> Self {
+        self.0.trips.insert(trip.id, trip);
+        self
+    }
+
+    pub fn build(self) -> Gtfs1 {
+        self.0
+    }
+}
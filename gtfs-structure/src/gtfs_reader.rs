@@ -158,9 +158,14 @@ impl RawGtfsReader {
             agencies: self.read_objs_from_path(p.join("agency.txt")),
             shapes: self.read_objs_from_optional_path(p, "shapes.txt"),
             fare_attributes: self.read_objs_from_optional_path(p, "fare_attributes.txt"),
+            fare_rules: self.read_objs_from_optional_path(p, "fare_rules.txt"),
+            fare_products: self.read_objs_from_optional_path(p, "fare_products.txt"),
+            fare_leg_rules: self.read_objs_from_optional_path(p, "fare_leg_rules.txt"),
+            stop_areas: self.read_objs_from_optional_path(p, "stop_areas.txt"),
             frequencies: self.read_objs_from_optional_path(p, "frequencies.txt"),
             transfers: self.read_objs_from_optional_path(p, "transfers.txt"),
             pathways: self.read_objs_from_optional_path(p, "pathways.txt"),
+            levels: self.read_objs_from_optional_path(p, "levels.txt"),
             feed_info: self.read_objs_from_optional_path(p, "feed_info.txt"),
             read_duration: Utc::now().signed_duration_since(now).num_milliseconds(),
             files,
@@ -243,9 +248,14 @@ impl RawGtfsReader {
                 "stop_times.txt",
                 "trips.txt",
                 "fare_attributes.txt",
+                "fare_rules.txt",
+                "fare_products.txt",
+                "fare_leg_rules.txt",
+                "stop_areas.txt",
                 "frequencies.txt",
                 "transfers.txt",
                 "pathways.txt",
+                "levels.txt",
                 "feed_info.txt",
                 "shapes.txt",
             ] {
@@ -278,9 +288,22 @@ impl RawGtfsReader {
                 &mut archive,
                 "fare_attributes.txt",
             ),
+            fare_rules: self.read_optional_file(&file_mapping, &mut archive, "fare_rules.txt"),
+            fare_products: self.read_optional_file(
+                &file_mapping,
+                &mut archive,
+                "fare_products.txt",
+            ),
+            fare_leg_rules: self.read_optional_file(
+                &file_mapping,
+                &mut archive,
+                "fare_leg_rules.txt",
+            ),
+            stop_areas: self.read_optional_file(&file_mapping, &mut archive, "stop_areas.txt"),
             frequencies: self.read_optional_file(&file_mapping, &mut archive, "frequencies.txt"),
             transfers: self.read_optional_file(&file_mapping, &mut archive, "transfers.txt"),
             pathways: self.read_optional_file(&file_mapping, &mut archive, "pathways.txt"),
+            levels: self.read_optional_file(&file_mapping, &mut archive, "levels.txt"),
             feed_info: self.read_optional_file(&file_mapping, &mut archive, "feed_info.txt"),
             shapes: self.read_optional_file(&file_mapping, &mut archive, "shapes.txt"),
             read_duration: Utc::now().signed_duration_since(now).num_milliseconds(),
@@ -356,6 +356,9 @@ pub struct Route {
     /// Indicates whether a rider can alight from the transit vehicle at any point along the vehicle’s travel path
     #[serde(default)]
     pub continuous_drop_off: ContinuousPickupDropOff,
+    /// Group of routes for fare leg rules (GTFS-Fares v2)
+    #[serde(default)]
+    pub network_id: Option<String>,
 }
 
 impl Type for Route {
@@ -564,7 +567,7 @@ impl Id for Shape {
 }
 
 /// Defines one possible fare. See <https://gtfs.org/reference/static/#fare_attributestxt>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FareAttribute {
     /// Unique technical (not for the traveller) identifier for the FareAttribute
     #[serde(rename = "fare_id")]
@@ -596,8 +599,58 @@ impl Type for FareAttribute {
     }
 }
 
+/// Rule for applying a [FareAttribute] to an itinerary. See <https://gtfs.org/reference/static/#fare_rulestxt>
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RawFareRule {
+    /// Identifies the [FareAttribute] this rule applies
+    pub fare_id: String,
+    /// The fare applies to this route
+    pub route_id: Option<String>,
+    /// The fare applies to departures from this zone
+    pub origin_id: Option<String>,
+    /// The fare applies to arrivals in this zone
+    pub destination_id: Option<String>,
+    /// The fare applies to itineraries passing through this zone
+    pub contains_id: Option<String>,
+}
+
+/// A fare product of GTFS-Fares v2. See <https://gtfs.org/reference/static/#fare_productstxt>
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RawFareProduct {
+    /// Identifies the fare product
+    pub fare_product_id: String,
+    /// Cost of the fare product
+    pub amount: String,
+    /// Currency of the cost of the fare product
+    pub currency: String,
+}
+
+/// Fare rule for individual legs of travel of GTFS-Fares v2. See <https://gtfs.org/reference/static/#fare_leg_rulestxt>
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RawFareLegRule {
+    /// Identifies a group of entries in fare_leg_rules.txt
+    pub leg_group_id: Option<String>,
+    /// Identifies a route network that applies for the fare leg rule
+    pub network_id: Option<String>,
+    /// Identifies a departure area
+    pub from_area_id: Option<String>,
+    /// Identifies an arrival area
+    pub to_area_id: Option<String>,
+    /// The fare product required to travel the leg
+    pub fare_product_id: String,
+}
+
+/// Assigns a stop to an area of GTFS-Fares v2. See <https://gtfs.org/reference/static/#stop_areastxt>
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RawStopArea {
+    /// Identifies an area
+    pub area_id: String,
+    /// Identifies a stop
+    pub stop_id: String,
+}
+
 /// A [Frequency] before being merged into the corresponding [Trip]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RawFrequency {
     /// References the [Trip] that uses frequency
     pub trip_id: String,
@@ -645,7 +698,7 @@ impl Frequency {
 }
 
 /// Transfer information between stops before merged into [Stop]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RawTransfer {
     /// Stop from which to leave
     pub from_stop_id: String,
@@ -727,7 +780,7 @@ impl fmt::Display for FeedInfo {
 }
 
 /// A graph representation to describe subway or train, with nodes (the locations) and edges (the pathways).
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct RawPathway {
     /// Uniquely identifies the pathway
     #[serde(rename = "pathway_id")]
@@ -757,6 +810,17 @@ pub struct RawPathway {
     pub reversed_signposted_as: Option<String>,
 }
 
+/// A level of a station. See <https://gtfs.org/reference/static/#levelstxt>
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RawLevel {
+    /// Identifies the level
+    pub level_id: String,
+    /// Numeric index of the level that indicates its relative position. Ground level is 0
+    pub level_index: f64,
+    /// Name of the level as seen by the rider inside the building or station
+    pub level_name: Option<String>,
+}
+
 /// Pathway going from a stop to another.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Pathway {
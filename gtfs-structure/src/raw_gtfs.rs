@@ -27,12 +27,22 @@ pub struct RawGtfs {
     pub shapes: Option<Result<Vec<Shape>, Error>>,
     /// All FareAttribates, None if the file was absent as it is not mandatory
     pub fare_attributes: Option<Result<Vec<FareAttribute>, Error>>,
+    /// All FareRules, None if the file was absent as it is not mandatory
+    pub fare_rules: Option<Result<Vec<RawFareRule>, Error>>,
+    /// All FareProducts (fares v2), None if the file was absent as it is not mandatory
+    pub fare_products: Option<Result<Vec<RawFareProduct>, Error>>,
+    /// All FareLegRules (fares v2), None if the file was absent as it is not mandatory
+    pub fare_leg_rules: Option<Result<Vec<RawFareLegRule>, Error>>,
+    /// All StopAreas (fares v2), None if the file was absent as it is not mandatory
+    pub stop_areas: Option<Result<Vec<RawStopArea>, Error>>,
     /// All Frequencies, None if the file was absent as it is not mandatory
     pub frequencies: Option<Result<Vec<RawFrequency>, Error>>,
     /// All Transfers, None if the file was absent as it is not mandatory
     pub transfers: Option<Result<Vec<RawTransfer>, Error>>,
     /// All Pathways, None if the file was absent as it is not mandatory
     pub pathways: Option<Result<Vec<RawPathway>, Error>>,
+    /// All Levels, None if the file was absent as it is not mandatory
+    pub levels: Option<Result<Vec<RawLevel>, Error>>,
     /// All FeedInfo, None if the file was absent as it is not mandatory
     pub feed_info: Option<Result<Vec<FeedInfo>, Error>>,
     /// All StopTimes
@@ -55,12 +65,23 @@ impl RawGtfs {
         println!("  Stop times: {}", mandatory_file_summary(&self.stop_times));
         println!("  Shapes: {}", optional_file_summary(&self.shapes));
         println!("  Fares: {}", optional_file_summary(&self.fare_attributes));
+        println!("  Fare rules: {}", optional_file_summary(&self.fare_rules));
+        println!(
+            "  Fare products: {}",
+            optional_file_summary(&self.fare_products)
+        );
+        println!(
+            "  Fare leg rules: {}",
+            optional_file_summary(&self.fare_leg_rules)
+        );
+        println!("  Stop areas: {}", optional_file_summary(&self.stop_areas));
         println!(
             "  Frequencies: {}",
             optional_file_summary(&self.frequencies)
         );
         println!("  Transfers: {}", optional_file_summary(&self.transfers));
         println!("  Pathways: {}", optional_file_summary(&self.pathways));
+        println!("  Levels: {}", optional_file_summary(&self.levels));
         println!("  Feed info: {}", optional_file_summary(&self.feed_info));
     }
 
@@ -1,4 +1,5 @@
-use crate::gtfs_setup::initialize_gtfs_as_bson;
+use crate::gtfs_setup::{initialize_gtfs_as_bson, load_gtfs};
+use chrono_tz::Tz;
 use gtfs_structure_2::gtfs_wrapper::Gtfs1;
 
 use rustc_hash::FxHashMap;
@@ -53,6 +54,17 @@ impl City {
 
         [coords.0, coords.1]
     }
+    pub fn get_timezone(&self) -> Tz {
+        match self {
+            City::NewYorkCity => chrono_tz::America::New_York,
+            City::Vancouver => chrono_tz::America::Vancouver,
+            City::Toronto => chrono_tz::America::Toronto,
+            City::Montreal => chrono_tz::America::Montreal,
+            City::Paris => chrono_tz::Europe::Paris,
+            City::SanFrancisco => chrono_tz::America::Los_Angeles,
+            City::Chicago => chrono_tz::America::Chicago,
+        }
+    }
     pub fn get_gpkg_path(&self) -> &'static str {
         match self {
             City::NewYorkCity => "NewYorkCity",
@@ -105,6 +117,36 @@ pub fn load_all_gtfs() -> FxHashMap<City, (Gtfs1, Vec<Agency>)> {
     result
 }
 
+/// Other versions of the cities' feeds, by name, each a directory next to the feed's named
+/// `<feed>@<version>`, e.g. `city-gtfs/ttc@2026-09`. Each holds the schedules of its agencies.
+pub fn load_feed_versions(city: City) -> Vec<(String, Vec<Gtfs1>)> {
+    let mut versions = Vec::new();
+    if !cfg!(feature = "prod") {
+        return versions;
+    }
+    let Ok(entries) = std::fs::read_dir("city-gtfs") else {
+        return versions;
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+
+    for (path, path_city) in gtfspaths() {
+        if path_city != city {
+            continue;
+        }
+        let prefix = format!("{path}@");
+        for name in names.iter().filter(|name| name.starts_with(&prefix)) {
+            let gtfs_list = load_gtfs(&format!("city-gtfs/{name}"), city);
+            versions.push((name.clone(), gtfs_list));
+        }
+    }
+    versions
+}
+
 pub fn gtfspaths() -> Vec<(&'static str, City)> {
     vec![
         ("ttc", City::Toronto),
@@ -34,7 +34,7 @@ impl<T: Hash + Copy + PartialEq + Eq + Serialize> BestTimes<T> {
     }
     pub fn set_best_time(&mut self, node: T, reach_data: ReachData) -> bool {
         match self.get_mut(&node) {
-            Some(x) if x.timestamp > reach_data.timestamp => {
+            Some(x) if x.cost > reach_data.cost => {
                 *x = reach_data;
                 true
             }
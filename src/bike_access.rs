@@ -0,0 +1,83 @@
+use crate::agencies::City;
+use crate::network::Network;
+use crate::projection;
+use crate::web::LatLng;
+use bike::{BikeLeg, Isochrone};
+use gtfs_structure_2::IdType;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+
+/// The origin must be this close to the bike network, in meters
+pub(crate) const MAX_ORIGIN_SNAP_M: f32 = 300.0;
+/// Stops within this distance of a reached bike node can be parked at, in meters
+const MAX_PARKING_DISTANCE_M: f64 = 150.0;
+/// Locking up the bike before walking to the stop
+const BIKE_PARKING_SECS: f64 = 60.0;
+/// Faster than anyone rides, to bound the stops that might be reached
+const MAX_CYCLING_SPEED: f64 = 8.0;
+
+pub struct BikeAccessStop {
+    pub stop_id: IdType,
+    /// Seconds from leaving the origin until walking up to the stop, including parking
+    pub secs: f64,
+    node: usize,
+}
+
+/// Stops reachable by bike from one origin, for bike-and-ride searches
+pub struct BikeAccess {
+    isochrone: Isochrone,
+    pub stops: Vec<BikeAccessStop>,
+}
+
+impl BikeAccess {
+    /// None if `origin` is not near the bike network
+    pub fn new(
+        graph: &bike::Graph,
+        city: &City,
+        network: &Network,
+        origin: LatLng,
+        max_cycling_secs: f64,
+        walking_speed: f64,
+    ) -> Option<BikeAccess> {
+        let isochrone = bike::isochrone(graph, &origin.into(), max_cycling_secs, MAX_ORIGIN_SNAP_M)?;
+
+        // Reached bike node -> (node ID, cycling seconds)
+        let nodes: RTree<GeomWithData<[f64; 2], (usize, f64)>> = RTree::bulk_load(
+            isochrone
+                .reached(graph)
+                .map(|(node, point, secs)| {
+                    let xy = projection::project_lng_lat(city, point.lon as f64, point.lat as f64);
+                    GeomWithData::new(xy, (node, secs))
+                })
+                .collect(),
+        );
+
+        let origin_xy = projection::project_lng_lat(city, origin.longitude, origin.latitude);
+        let max_distance = MAX_CYCLING_SPEED * max_cycling_secs;
+        let stops = network
+            .stops_near(&origin_xy, max_distance)
+            .into_iter()
+            .filter_map(|(stop, _)| {
+                let (node, distance_2) = nodes.nearest_neighbor_iter_with_distance_2(stop.geom()).next()?;
+                let distance = distance_2.sqrt();
+                if distance > MAX_PARKING_DISTANCE_M {
+                    return None;
+                }
+                let (node_id, cycling_secs) = node.data;
+                Some(BikeAccessStop {
+                    stop_id: stop.data.stop_id,
+                    secs: cycling_secs + BIKE_PARKING_SECS + distance / walking_speed,
+                    node: node_id,
+                })
+            })
+            .collect();
+
+        Some(BikeAccess { isochrone, stops })
+    }
+
+    /// The ride from the origin to where the bike is parked for `stop_id`
+    pub fn leg_to(&self, graph: &bike::Graph, stop_id: IdType) -> Option<BikeLeg> {
+        let stop = self.stops.iter().find(|stop| stop.stop_id == stop_id)?;
+        self.isochrone.leg_to(graph, stop.node)
+    }
+}
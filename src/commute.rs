@@ -0,0 +1,101 @@
+use crate::configuration::Configuration;
+use crate::network::Network;
+use crate::road_structure::{EdgeId, RoadStructure, RoadStructureInner};
+use crate::time::Time;
+use crate::time_to_reach;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::trip_details::CalculateRequest;
+
+pub const MAX_DESTINATIONS: usize = 5;
+
+/// How travel times to each destination are combined into one score per edge
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum CombineMethod {
+    Sum,
+    WeightedSum,
+    Max,
+    Min,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommuteDestination {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Only used by weightedSum
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+#[derive(Deserialize)]
+pub struct CommuteRequest {
+    pub destinations: Vec<CommuteDestination>,
+    pub combine: CombineMethod,
+    /// Search shared by all destinations. Use "arriveBy" to score the time needed to get to each
+    /// destination by `startTime`. Its coordinates are ignored.
+    pub search: CalculateRequest,
+}
+
+/// Runs one search per destination and combines the travel times per edge. Only `min` keeps edges
+/// that don't reach every destination.
+pub fn generate_commute_scores(
+    network: &Network,
+    rs_template: &Arc<RoadStructureInner>,
+    configs: Vec<Configuration>,
+    destinations: &[CommuteDestination],
+    combine: CombineMethod,
+) -> FxHashMap<EdgeId, f64> {
+    let destination_count = configs.len();
+    // Edge ID -> (combined score, number of destinations reached)
+    let mut scores: FxHashMap<EdgeId, (f64, usize)> = FxHashMap::default();
+
+    for (config, destination) in configs.into_iter().zip(destinations) {
+        let search_mode = config.search_mode;
+        let search_start = search_mode.to_search_time(config.start_time);
+        let mut rs = RoadStructure::new_from_road_structure(rs_template.clone());
+        time_to_reach::generate_reach_times(network, &mut rs, config);
+
+        for edge_time in rs.save() {
+            let travel_time = (search_mode.to_search_time(Time(edge_time.time)) - search_start).0;
+            let (score, reached) = scores
+                .entry(edge_time.edge_id)
+                .or_insert((combine.initial_score(), 0));
+            *score = combine.combine(*score, travel_time, destination.weight);
+            *reached += 1;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter(|(_, (_, reached))| {
+            matches!(combine, CombineMethod::Min) || *reached == destination_count
+        })
+        .map(|(edge_id, (score, _))| (edge_id, score))
+        .collect()
+}
+
+impl CombineMethod {
+    fn initial_score(self) -> f64 {
+        match self {
+            CombineMethod::Sum | CombineMethod::WeightedSum => 0.0,
+            CombineMethod::Max => f64::NEG_INFINITY,
+            CombineMethod::Min => f64::INFINITY,
+        }
+    }
+
+    fn combine(self, score: f64, travel_time: f64, weight: f64) -> f64 {
+        match self {
+            CombineMethod::Sum => score + travel_time,
+            CombineMethod::WeightedSum => score + weight * travel_time,
+            CombineMethod::Max => score.max(travel_time),
+            CombineMethod::Min => score.min(travel_time),
+        }
+    }
+}
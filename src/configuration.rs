@@ -1,13 +1,222 @@
+use crate::bike_access::BikeAccess;
+use crate::realtime::RealtimeOverlay;
 use crate::time::Time;
 use crate::web::LatLng;
+use crate::{STRAIGHT_WALKING_SPEED, WALKING_SPEED};
+use chrono::NaiveDate;
+use chrono_tz::Tz;
 use gtfs_structure_2::gtfs_wrapper::RouteType;
-use rustc_hash::FxHashSet;
+use gtfs_structure_2::IdType;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+use std::sync::Arc;
 
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchMode {
+    /// Where can I get to, leaving `start_time` from `location`
+    #[default]
+    DepartAt,
+    /// From where can I reach `location` by `start_time`, and when do I have to leave
+    ArriveBy,
+}
+
+impl Configuration {
+    /// `location` followed by `extra_origins`, indexed as in `InProgressTrip::origin`
+    pub fn origins(&self) -> impl Iterator<Item = LatLng> + '_ {
+        std::iter::once(self.location).chain(self.extra_origins.iter().copied())
+    }
+}
+
+impl SearchMode {
+    /// Maps a clock time onto the timeline the search explores in increasing order.
+    /// Arrive-by searches run backwards in clock time, so their timeline is the negated clock.
+    pub fn to_search_time(self, time: Time) -> Time {
+        match self {
+            SearchMode::DepartAt => time,
+            SearchMode::ArriveBy => Time(-time.0),
+        }
+    }
+
+    pub fn to_clock_time(self, search_time: Time) -> Time {
+        // Negation is its own inverse
+        self.to_search_time(search_time)
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchEngine {
+    /// Priority queue search over trips, pruning stops that were reached earlier
+    #[default]
+    Heuristic,
+    /// Round-based search (RAPTOR). Gives earliest arrivals for every number of transfers, but
+    /// ignores the transfer penalty and only departs at a time.
+    Raptor,
+}
+
+/// How the whole trip is made
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum TravelMode {
+    /// Walk or cycle to stops, then take transit
+    #[default]
+    Transit,
+    /// Only walk along the road network
+    Walk,
+    /// Only cycle along the bike network
+    Bike,
+}
+
+/// How the origin gets to its first stop
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AccessMode {
+    #[default]
+    Walk,
+    /// Cycle over the bike network to any stop, then park and take transit
+    Bike,
+}
+
+/// Walking assumptions of a search
+#[derive(Debug, Clone, Copy)]
+pub struct WalkingParameters {
+    /// Speed walked along roads, in m/s
+    pub speed: f64,
+    /// Speed over straight-line distances, in m/s. Lower than `speed` to account for detours.
+    pub straight_speed: f64,
+    /// Maximum straight-line distance walked to reach a stop, in meters
+    pub max_transfer_distance_m: f64,
+    /// Maximum time walked along roads after leaving the origin or a stop
+    pub max_walking_secs: f64,
+    /// Road nodes within this distance of a point are walked to directly, in meters
+    pub snap_distance_m: f64,
+    /// Generalized cost of a second walked
+    pub cost_weight: f64,
+}
+
+impl Default for WalkingParameters {
+    fn default() -> Self {
+        WalkingParameters {
+            speed: WALKING_SPEED,
+            straight_speed: STRAIGHT_WALKING_SPEED,
+            max_transfer_distance_m: 800.0,
+            max_walking_secs: 0.40 * 3600.0,
+            snap_distance_m: 100.0,
+            cost_weight: 1.0,
+        }
+    }
+}
+
+impl WalkingParameters {
+    /// Walking parameters for someone walking at `speed` m/s
+    pub fn with_speed(self, speed: f64) -> Self {
+        WalkingParameters {
+            speed,
+            straight_speed: speed * STRAIGHT_WALKING_SPEED / WALKING_SPEED,
+            ..self
+        }
+    }
+}
+
+/// Generalized cost of riding one mode
+#[derive(Debug, Clone, Copy)]
+pub struct ModeCost {
+    /// Multiplies the in-vehicle weight of the cost model
+    pub weight: f64,
+    /// Seconds added when getting on a vehicle of this mode
+    pub boarding_penalty: f64,
+    /// Seconds added when getting off
+    pub alighting_penalty: f64,
+}
+
+impl Default for ModeCost {
+    fn default() -> Self {
+        ModeCost {
+            weight: 1.0,
+            boarding_penalty: 0.0,
+            alighting_penalty: 0.0,
+        }
+    }
+}
+
+/// Weights of the generalized cost the search minimizes, in weighted seconds. Walking is weighed by
+/// `WalkingParameters::cost_weight`. By default every weight is 1, so the cost is the travel time.
+#[derive(Debug, Clone)]
+pub struct CostModel {
+    pub in_vehicle_weight: f64,
+    /// Weight of the time spent waiting at stops, including the minimum transfer time
+    pub wait_weight: f64,
+    pub modes: FxHashMap<RouteType, ModeCost>,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            in_vehicle_weight: 1.0,
+            wait_weight: 1.0,
+            modes: FxHashMap::default(),
+        }
+    }
+}
+
+impl CostModel {
+    pub fn mode(&self, route_type: &RouteType) -> ModeCost {
+        self.modes.get(route_type).copied().unwrap_or_default()
+    }
+}
+
+/// Parts of the network a search must not use, for instance during closures
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    pub routes: FxHashSet<IdType>,
+    /// Stops that can't be boarded or left at, including every stop of the avoided area.
+    /// Vehicles still run through them.
+    pub stops: FxHashSet<IdType>,
+    pub trips: FxHashSet<IdType>,
+    /// (route, stop) pairs where the route can't be boarded or left at, while other routes still serve the stop
+    pub route_stops: FxHashSet<(IdType, IdType)>,
+}
+
+pub const DEFAULT_MAX_TRANSFERS: u8 = 3;
+pub const MAX_ORIGINS: usize = 8;
+pub const DEFAULT_MAX_CYCLING_SECS: f64 = 15.0 * 60.0;
+
+#[derive(Clone)]
 pub struct Configuration {
     pub start_time: Time,
     pub duration_secs: f64,
     pub location: LatLng,
+    /// Other origins searched at the same time. Reach times are the minimum over all origins.
+    pub extra_origins: Vec<LatLng>,
     pub agency_ids: FxHashSet<u16>,
     pub modes: Vec<RouteType>,
-    pub(crate) transfer_cost: u64
+    pub(crate) transfer_cost: u64,
+    /// Service day that `start_time` is relative to. Trips are filtered by the GTFS calendar of this day.
+    pub service_date: NaiveDate,
+    /// Local timezone of the city's feeds, used to resolve the service date.
+    pub timezone: Tz,
+    /// In arrive-by mode, `start_time` is the arrival deadline and `location` is the destination.
+    pub search_mode: SearchMode,
+    /// Profile queries search every departure minute from `start_time` to `start_time + profile_window_secs`
+    pub profile_window_secs: Option<f64>,
+    /// Only use stops and trips that aren't marked as inaccessible by wheelchair
+    pub wheelchair: bool,
+    pub walking: WalkingParameters,
+    /// Vehicles boarded after the first one. Free transfers within a block don't count.
+    pub max_transfers: u8,
+    /// Keep Pareto-optimal (time, transfers) labels instead of only the earliest arrival
+    pub pareto_transfers: bool,
+    pub engine: SearchEngine,
+    pub travel_mode: TravelMode,
+    pub cost: CostModel,
+    /// Journeys whose fare is above this are not explored further. Agencies without fare data are free.
+    pub max_fare: Option<f64>,
+    pub exclusions: Exclusions,
+    /// Delays, cancellations, added trips and alerts from the realtime feeds. None to only use the schedule.
+    pub realtime: Option<Arc<RealtimeOverlay>>,
+    /// POSIX time of the start of the search, which realtime alerts are active at
+    pub alerts_time: i64,
+    /// Stops cycled to from each origin, when biking to transit. Empty when walking.
+    pub bike_access: Vec<Arc<BikeAccess>>,
 }
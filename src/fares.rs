@@ -0,0 +1,551 @@
+use crate::configuration::SearchMode;
+use crate::in_progress_trip::InProgressTrip;
+use crate::network::Network;
+use crate::time::Time;
+use crate::NULL_ID;
+use gtfs_structure_2::fares::{FareLegRule, Fares};
+use gtfs_structure_2::gtfs_wrapper::AgencyId;
+use gtfs_structure_2::IdType;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Fare {
+    pub amount: f64,
+    /// None when only walking or cycling
+    pub currency: Option<String>,
+}
+
+/// Riding one vehicle from boarding to getting off. Staying on within a block is the same leg.
+#[derive(Debug, Clone)]
+struct Leg {
+    agency_id: AgencyId,
+    route_id: IdType,
+    boarding_stop_id: IdType,
+    exit_stop_id: IdType,
+    boarding_time: f64,
+    /// Fare zones of every stop passed, including both ends
+    zones: Vec<IdType>,
+}
+
+/// A fare_attributes ticket still valid for transfers
+struct Ticket {
+    fare_id: IdType,
+    bought_at: f64,
+    transfers_used: u32,
+}
+
+/// What a leg can be paid with, from its agency's fare data
+#[derive(Debug, Clone)]
+enum LegFares {
+    /// fare_attributes fares whose rules match the leg
+    Attributes(Vec<IdType>),
+    /// Fares v2 product of the leg's most specific leg rule
+    Product(IdType),
+    /// The agency has no fare data matching the leg
+    Unpriced,
+}
+
+/// A leg matched against its agency's fare rules
+#[derive(Debug, Clone)]
+struct MatchedLeg {
+    agency_id: AgencyId,
+    boarding_time: f64,
+    fares: LegFares,
+}
+
+#[derive(Default)]
+struct Priced {
+    amount: f64,
+    currencies: Vec<String>,
+    /// Some leg belongs to an agency without matching fare data
+    unpriced: bool,
+}
+
+impl Priced {
+    fn add(&mut self, amount: f64, currency: &str) {
+        self.amount += amount;
+        if !self.currencies.iter().any(|c| c == currency) {
+            self.currencies.push(currency.to_string());
+        }
+    }
+}
+
+fn zones_along(network: &Network, trip: &InProgressTrip) -> Vec<IdType> {
+    // Trips added by realtime feeds aren't in the schedule, so only their ends are known
+    let Some(gtfs_trip) = network.find_trip(trip.trip_id) else {
+        return [trip.boarding_stop_id, trip.get_off_stop_id]
+            .iter()
+            .filter_map(|stop_id| network.stop(*stop_id).zone_id)
+            .collect();
+    };
+    gtfs_trip.stop_times[trip.boarding_stop_time_idx..=trip.get_off_stop_time_idx]
+        .iter()
+        .filter_map(|st| network.stop(st.stop_id).zone_id)
+        .collect()
+}
+
+fn legs(network: &Network, trips: &[&InProgressTrip]) -> Vec<Leg> {
+    let mut transit: Vec<&InProgressTrip> = trips
+        .iter()
+        .copied()
+        .filter(|trip| trip.trip_id != NULL_ID && trip.current_route.route_id != NULL_ID)
+        .collect();
+    transit.sort_by(|a, b| a.boarding_time.0.total_cmp(&b.boarding_time.0));
+
+    let mut legs: Vec<Leg> = Vec::new();
+    let mut previous_transfers = None;
+    for trip in transit {
+        let continues_block = previous_transfers == Some(trip.total_transfers);
+        previous_transfers = Some(trip.total_transfers);
+        match legs.last_mut() {
+            Some(leg) if continues_block => {
+                leg.exit_stop_id = trip.get_off_stop_id;
+                leg.zones.extend(zones_along(network, trip));
+            }
+            _ => legs.push(Leg {
+                agency_id: trip.current_route.route_id.0,
+                route_id: trip.current_route.route_id,
+                boarding_stop_id: trip.boarding_stop_id,
+                exit_stop_id: trip.get_off_stop_id,
+                boarding_time: trip.boarding_time.0,
+                zones: zones_along(network, trip),
+            }),
+        }
+    }
+    legs
+}
+
+// Fares whose fare_rules match the leg. Fares without any rule apply to every leg of their agency.
+fn matching_fare_ids(network: &Network, fares: &Fares, leg: &Leg) -> Vec<IdType> {
+    let origin_zone = network.stop(leg.boarding_stop_id).zone_id;
+    let destination_zone = network.stop(leg.exit_stop_id).zone_id;
+
+    let mut ids: Vec<IdType> = fares
+        .rules
+        .iter()
+        .filter(|rule| {
+            rule.route_id.map_or(true, |id| id == leg.route_id)
+                && rule.origin_id.map_or(true, |id| Some(id) == origin_zone)
+                && rule.destination_id.map_or(true, |id| Some(id) == destination_zone)
+                && rule.contains_id.map_or(true, |id| leg.zones.contains(&id))
+        })
+        .map(|rule| rule.fare_id)
+        .collect();
+    ids.extend(
+        fares
+            .attributes
+            .keys()
+            .filter(|id| !fares.rules.iter().any(|rule| rule.fare_id == **id)),
+    );
+    ids.retain(|id| fares.attributes.contains_key(id));
+    ids
+}
+
+fn price_fare_attributes(
+    fares: &Fares,
+    leg: &MatchedLeg,
+    fare_ids: &[IdType],
+    tickets: &mut FxHashMap<AgencyId, Ticket>,
+    priced: &mut Priced,
+) {
+    if let Some(ticket) = tickets.get_mut(&leg.agency_id) {
+        let fare = &fares.attributes[&ticket.fare_id];
+        let can_transfer = fare_ids.contains(&ticket.fare_id)
+            && fare.transfers.map_or(true, |max| ticket.transfers_used < max)
+            && fare
+                .transfer_duration
+                .map_or(true, |secs| leg.boarding_time - ticket.bought_at <= secs as f64);
+        if can_transfer {
+            ticket.transfers_used += 1;
+            return;
+        }
+    }
+
+    let cheapest = fare_ids
+        .iter()
+        .map(|id| &fares.attributes[id])
+        .min_by(|a, b| a.price.total_cmp(&b.price));
+    match cheapest {
+        Some(fare) => {
+            priced.add(fare.price, &fare.currency);
+            tickets.insert(
+                leg.agency_id,
+                Ticket {
+                    fare_id: fare.id,
+                    bought_at: leg.boarding_time,
+                    transfers_used: 0,
+                },
+            );
+        }
+        None => priced.unpriced = true,
+    }
+}
+
+fn leg_rule_specificity(rule: &FareLegRule) -> usize {
+    [rule.network_id, rule.from_area_id, rule.to_area_id]
+        .iter()
+        .filter(|id| id.is_some())
+        .count()
+}
+
+// Fares v2 without fare_transfer_rules: every leg pays the product of its most specific leg rule
+fn leg_product(network: &Network, fares: &Fares, leg: &Leg) -> Option<IdType> {
+    let network_id = network.route(leg.route_id).network_id;
+    let in_area = |stop_id: &IdType, area_id: IdType| {
+        fares
+            .stop_areas
+            .get(stop_id)
+            .is_some_and(|areas| areas.contains(&area_id))
+    };
+
+    let matching: Vec<&FareLegRule> = fares
+        .leg_rules
+        .iter()
+        .filter(|rule| {
+            rule.network_id.map_or(true, |id| Some(id) == network_id)
+                && rule.from_area_id.map_or(true, |id| in_area(&leg.boarding_stop_id, id))
+                && rule.to_area_id.map_or(true, |id| in_area(&leg.exit_stop_id, id))
+        })
+        .collect();
+    let specificity = matching.iter().map(|rule| leg_rule_specificity(rule)).max()?;
+
+    matching
+        .into_iter()
+        .filter(|rule| leg_rule_specificity(rule) == specificity)
+        .filter_map(|rule| fares.products.get(&rule.fare_product_id))
+        .min_by(|a, b| a.amount.total_cmp(&b.amount))
+        .map(|product| product.id)
+}
+
+fn match_leg(network: &Network, leg: &Leg) -> MatchedLeg {
+    let fares = match network.fares(leg.agency_id).filter(|fares| !fares.is_empty()) {
+        None => LegFares::Unpriced,
+        // Feeds with both formats are priced by the newer one
+        Some(fares) if fares.leg_rules.is_empty() => LegFares::Attributes(matching_fare_ids(network, fares, leg)),
+        Some(fares) => leg_product(network, fares, leg).map_or(LegFares::Unpriced, LegFares::Product),
+    };
+    MatchedLeg {
+        agency_id: leg.agency_id,
+        boarding_time: leg.boarding_time,
+        fares,
+    }
+}
+
+fn price(network: &Network, mut legs: Vec<&MatchedLeg>) -> Priced {
+    legs.sort_by(|a, b| a.boarding_time.total_cmp(&b.boarding_time));
+    let mut priced = Priced::default();
+    let mut tickets = FxHashMap::default();
+
+    for leg in legs {
+        let fares = network.fares(leg.agency_id);
+        match (&leg.fares, fares) {
+            (LegFares::Attributes(fare_ids), Some(fares)) => {
+                price_fare_attributes(fares, leg, fare_ids, &mut tickets, &mut priced)
+            }
+            (LegFares::Product(product_id), Some(fares)) => {
+                let product = &fares.products[product_id];
+                priced.add(product.amount, &product.currency);
+            }
+            _ => priced.unpriced = true,
+        }
+    }
+    priced
+}
+
+/// Fare of a journey given its in-progress trips in any order. Each agency is paid separately,
+/// with free transfers while a fare_attributes ticket allows them. None if some leg can't be priced
+/// or the journey mixes currencies.
+pub fn journey_fare(network: &Network, trips: &[&InProgressTrip]) -> Option<Fare> {
+    let legs: Vec<MatchedLeg> = legs(network, trips).iter().map(|leg| match_leg(network, leg)).collect();
+    let priced = price(network, legs.iter().collect());
+    match (priced.unpriced, priced.currencies.as_slice()) {
+        (false, [currency]) => Some(Fare {
+            amount: priced.amount,
+            currency: Some(currency.clone()),
+        }),
+        (false, []) => Some(Fare {
+            amount: 0.0,
+            currency: None,
+        }),
+        _ => None,
+    }
+}
+
+/// Fare data carried along a journey while searching with a max fare, so that each stop only
+/// matches the leg it ends against the fare rules
+#[derive(Debug)]
+pub struct FareProgress {
+    /// Legs ridden before the current one, or after it when arriving by a time
+    ridden: Arc<Vec<MatchedLeg>>,
+    /// The leg ending at the trip's frontier. Trips continuing in a block extend it.
+    current: Leg,
+}
+
+/// Prices a journey riding one more vehicle, for each stop it can be ridden to (or boarded at,
+/// when arriving by a time). Legs that can't be priced count as free, so agencies without fare
+/// data are never excluded.
+pub(crate) struct NextLeg {
+    ridden: Arc<Vec<MatchedLeg>>,
+    leg: Leg,
+    search_mode: SearchMode,
+    max_fare: f64,
+}
+
+impl NextLeg {
+    /// `stop_id` and `time` are where the vehicle is boarded, or left when arriving by a time
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        network: &Network,
+        previous: &InProgressTrip,
+        continues_block: bool,
+        route_id: IdType,
+        stop_id: IdType,
+        time: Time,
+        search_mode: SearchMode,
+        max_fare: f64,
+    ) -> NextLeg {
+        let zone = network.stop(stop_id).zone_id;
+        let (ridden, leg) = match previous.fare.as_deref() {
+            Some(progress) if continues_block => {
+                let mut leg = progress.current.clone();
+                // Merged legs are priced by the route of their first vehicle
+                if search_mode == SearchMode::ArriveBy {
+                    leg.agency_id = route_id.0;
+                    leg.route_id = route_id;
+                }
+                leg.zones.extend(zone);
+                (progress.ridden.clone(), leg)
+            }
+            progress => {
+                let mut ridden = progress.map_or_else(Vec::new, |progress| progress.ridden.as_ref().clone());
+                ridden.extend(progress.map(|progress| match_leg(network, &progress.current)));
+                let leg = Leg {
+                    agency_id: route_id.0,
+                    route_id,
+                    boarding_stop_id: stop_id,
+                    exit_stop_id: stop_id,
+                    boarding_time: time.0,
+                    zones: zone.into_iter().collect(),
+                };
+                (Arc::new(ridden), leg)
+            }
+        };
+        NextLeg {
+            ridden,
+            leg,
+            search_mode,
+            max_fare,
+        }
+    }
+
+    /// Rides through a stop, whether or not it can be left at
+    pub(crate) fn pass(&mut self, network: &Network, stop_id: IdType) {
+        self.leg.zones.extend(network.stop(stop_id).zone_id);
+    }
+
+    /// Ends the leg at a stop already passed. None if the journey then costs more than the max fare.
+    pub(crate) fn end_at(&mut self, network: &Network, stop_id: IdType, time: Time) -> Option<Arc<FareProgress>> {
+        match self.search_mode {
+            SearchMode::DepartAt => self.leg.exit_stop_id = stop_id,
+            SearchMode::ArriveBy => {
+                self.leg.boarding_stop_id = stop_id;
+                self.leg.boarding_time = time.0;
+            }
+        }
+        let current = match_leg(network, &self.leg);
+        let legs = self.ridden.iter().chain([&current]).collect();
+        if price(network, legs).amount > self.max_fare {
+            return None;
+        }
+        Some(Arc::new(FareProgress {
+            ridden: self.ridden.clone(),
+            current: self.leg.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gtfs_processing::SpatialStopsWithTrips;
+    use gtfs_structure_2::fares::{FareAttribute, FareProduct, FareRule};
+    use gtfs_structure_2::gtfs_wrapper::{Gtfs1, Route, Stop};
+    use gtfs_structure_2::test_gtfs::{test_trip, TestGtfs};
+
+    // Trip (0, 1) of route (0, 1) calls at stops 1 to 3, each in its own zone
+    fn fares_test_gtfs(fares: Fares) -> Gtfs1 {
+        let zone = |stop: u64| Stop {
+            zone_id: Some((0, stop * 10)),
+            ..Default::default()
+        };
+        let route = Route {
+            network_id: Some((0, 100)),
+            ..Default::default()
+        };
+        let mut gtfs = TestGtfs::default()
+            .stop(1, zone(1))
+            .stop(2, zone(2))
+            .stop(3, zone(3))
+            .route(1, route)
+            .trip(test_trip(1, 1, &[(1, Some(0)), (2, Some(300)), (3, Some(600))]))
+            .build();
+        gtfs.fares.insert(0, fares);
+        gtfs
+    }
+
+    // Riding trip (0, 1) between two of its stops, numbered from 1
+    fn ride(from: u64, to: u64, boarding_time: f64, total_transfers: u8) -> InProgressTrip {
+        InProgressTrip {
+            total_transfers,
+            boarding_stop_time_idx: from as usize - 1,
+            get_off_stop_time_idx: to as usize - 1,
+            ..InProgressTrip::test_ride((0, from), (0, to), boarding_time, boarding_time + 600.0)
+        }
+    }
+
+    fn cad(amount: f64) -> Option<Fare> {
+        Some(Fare {
+            amount,
+            currency: Some("CAD".to_string()),
+        })
+    }
+
+    fn fare_attribute(id: u64, price: f64, transfer_duration: Option<u32>) -> (IdType, FareAttribute) {
+        let attribute = FareAttribute {
+            id: (0, id),
+            price,
+            currency: "CAD".to_string(),
+            transfers: None,
+            transfer_duration,
+        };
+        ((0, id), attribute)
+    }
+
+    #[test]
+    fn test_fare_rules_zones() {
+        let fares = Fares {
+            attributes: [fare_attribute(1, 2.0, None), fare_attribute(2, 3.5, None)].into_iter().collect(),
+            rules: vec![
+                FareRule {
+                    fare_id: (0, 1),
+                    route_id: None,
+                    origin_id: Some((0, 10)),
+                    destination_id: Some((0, 20)),
+                    contains_id: None,
+                },
+                FareRule {
+                    fare_id: (0, 2),
+                    route_id: None,
+                    origin_id: None,
+                    destination_id: None,
+                    contains_id: Some((0, 30)),
+                },
+            ],
+            ..Default::default()
+        };
+        let gtfs = fares_test_gtfs(fares);
+        let spatial = SpatialStopsWithTrips::default();
+        let network = Network::new(&gtfs, &spatial);
+
+        assert_eq!(journey_fare(&network, &[&ride(1, 2, 0.0, 1)]), cad(2.0));
+        // Passing through zone 30 only matches the second fare
+        assert_eq!(journey_fare(&network, &[&ride(1, 3, 0.0, 1)]), cad(3.5));
+        // No rule goes from zone 20 to 30 without passing through it
+        assert_eq!(journey_fare(&network, &[&ride(2, 3, 0.0, 1)]), cad(3.5));
+    }
+
+    #[test]
+    fn test_fare_transfer_expiry() {
+        let fares = Fares {
+            attributes: [fare_attribute(1, 2.0, Some(3600))].into_iter().collect(),
+            ..Default::default()
+        };
+        let gtfs = fares_test_gtfs(fares);
+        let spatial = SpatialStopsWithTrips::default();
+        let network = Network::new(&gtfs, &spatial);
+
+        let transfer = [ride(1, 2, 0.0, 1), ride(2, 3, 1800.0, 2)];
+        assert_eq!(journey_fare(&network, &transfer.iter().collect::<Vec<_>>()), cad(2.0));
+        let expired = [ride(1, 2, 0.0, 1), ride(2, 3, 4000.0, 2)];
+        assert_eq!(journey_fare(&network, &expired.iter().collect::<Vec<_>>()), cad(4.0));
+        // Staying on within a block is the same leg, whenever it continues
+        let block = [ride(1, 2, 0.0, 1), ride(2, 3, 4000.0, 1)];
+        assert_eq!(journey_fare(&network, &block.iter().collect::<Vec<_>>()), cad(2.0));
+    }
+
+    #[test]
+    fn test_fare_leg_rule_specificity() {
+        let leg_rule = |network_id, from_area_id, fare_product_id| FareLegRule {
+            network_id,
+            from_area_id,
+            to_area_id: None,
+            fare_product_id,
+        };
+        let product = |id, amount| {
+            let product = FareProduct {
+                id,
+                amount,
+                currency: "CAD".to_string(),
+            };
+            (id, product)
+        };
+        let fares = Fares {
+            products: [product((0, 1), 5.0), product((0, 2), 3.0), product((0, 3), 4.0)]
+                .into_iter()
+                .collect(),
+            leg_rules: vec![
+                leg_rule(None, None, (0, 1)),
+                leg_rule(Some((0, 100)), None, (0, 2)),
+                leg_rule(Some((0, 100)), Some((0, 7)), (0, 3)),
+            ],
+            stop_areas: [((0, 1), vec![(0, 7)])].into_iter().collect(),
+            ..Default::default()
+        };
+        let gtfs = fares_test_gtfs(fares);
+        let spatial = SpatialStopsWithTrips::default();
+        let network = Network::new(&gtfs, &spatial);
+
+        // The rule naming the area wins, even though the network's rule is cheaper
+        assert_eq!(journey_fare(&network, &[&ride(1, 3, 0.0, 1)]), cad(4.0));
+        assert_eq!(journey_fare(&network, &[&ride(2, 3, 0.0, 1)]), cad(3.0));
+    }
+
+    #[test]
+    fn test_next_leg_max_fare() {
+        let fares = Fares {
+            attributes: [fare_attribute(1, 2.0, Some(3600))].into_iter().collect(),
+            ..Default::default()
+        };
+        let gtfs = fares_test_gtfs(fares);
+        let spatial = SpatialStopsWithTrips::default();
+        let network = Network::new(&gtfs, &spatial);
+        let origin = ride(1, 1, 0.0, 0);
+
+        let mut first = NextLeg::new(&network, &origin, false, (0, 1), (0, 1), Time(0.0), SearchMode::DepartAt, 3.0);
+        first.pass(&network, (0, 2));
+        let first_leg = InProgressTrip {
+            fare: first.end_at(&network, (0, 2), Time(600.0)),
+            ..ride(1, 2, 0.0, 1)
+        };
+        assert!(first_leg.fare.is_some());
+
+        // Same as journey_fare: transferring in time is free, and later costs another 2.0
+        for (boarding_time, within_max_fare) in [(1800.0, true), (4000.0, false)] {
+            let mut second = NextLeg::new(
+                &network,
+                &first_leg,
+                false,
+                (0, 1),
+                (0, 2),
+                Time(boarding_time),
+                SearchMode::DepartAt,
+                3.0,
+            );
+            second.pass(&network, (0, 3));
+            let fare = second.end_at(&network, (0, 3), Time(boarding_time + 600.0));
+            assert_eq!(fare.is_some(), within_max_fare);
+        }
+    }
+}
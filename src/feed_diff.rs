@@ -0,0 +1,84 @@
+use crate::configuration::Configuration;
+use crate::network::Network;
+use crate::road_structure::{EdgeId, RoadStructure, RoadStructureInner};
+use crate::time::Time;
+use crate::time_to_reach;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::trip_details::CalculateRequest;
+
+#[derive(Deserialize)]
+pub struct DiffRequest {
+    /// Search run against both networks. Its scenario is ignored.
+    pub search: CalculateRequest,
+    /// Scenario or feed version to compare from, e.g. "ttc@2026-06". None for the current feeds.
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// How travel times change from one network to the other
+#[derive(Serialize, Default, Debug)]
+pub struct DiffSummary {
+    /// Edges reached with both networks
+    pub compared: usize,
+    pub faster: usize,
+    pub slower: usize,
+    /// Edges only reached with the network after, or before
+    pub newly_reached: usize,
+    pub no_longer_reached: usize,
+    /// Seconds that travel times change by, over the edges compared
+    pub mean_delta: f64,
+    pub median_delta: f64,
+}
+
+/// Edge ID -> seconds from the start of the search
+pub fn travel_times(
+    network: &Network,
+    rs_template: &Arc<RoadStructureInner>,
+    config: Configuration,
+) -> FxHashMap<EdgeId, f64> {
+    let search_mode = config.search_mode;
+    let search_start = search_mode.to_search_time(config.start_time);
+    let mut rs = RoadStructure::new_from_road_structure(rs_template.clone());
+    time_to_reach::generate_reach_times(network, &mut rs, config);
+
+    rs.save()
+        .into_iter()
+        .map(|edge_time| {
+            let travel_time = (search_mode.to_search_time(Time(edge_time.time)) - search_start).0;
+            (edge_time.edge_id, travel_time)
+        })
+        .collect()
+}
+
+/// Change in travel time of each edge reached with both networks, negative when it got faster
+pub fn diff_travel_times(
+    before: &FxHashMap<EdgeId, f64>,
+    after: &FxHashMap<EdgeId, f64>,
+) -> (FxHashMap<EdgeId, i32>, DiffSummary) {
+    let deltas: FxHashMap<EdgeId, i32> = after
+        .iter()
+        .filter_map(|(edge_id, after_time)| {
+            let before_time = before.get(edge_id)?;
+            Some((*edge_id, (after_time - before_time).round() as i32))
+        })
+        .collect();
+
+    let mut sorted: Vec<i32> = deltas.values().copied().collect();
+    sorted.sort_unstable();
+    let mut summary = DiffSummary {
+        compared: sorted.len(),
+        faster: sorted.iter().filter(|delta| **delta < 0).count(),
+        slower: sorted.iter().filter(|delta| **delta > 0).count(),
+        newly_reached: after.len() - deltas.len(),
+        no_longer_reached: before.len() - deltas.len(),
+        ..Default::default()
+    };
+    if !sorted.is_empty() {
+        summary.mean_delta = sorted.iter().map(|delta| *delta as f64).sum::<f64>() / sorted.len() as f64;
+        summary.median_delta = sorted[sorted.len() / 2] as f64;
+    }
+    (deltas, summary)
+}
@@ -1,8 +1,10 @@
 use crate::in_progress_trip::InProgressTrip;
+use crate::network::Network;
+use crate::realtime::RealtimeOverlay;
 use crate::time::Time;
 use crate::trips_arena::TripsArena;
-use crate::{gtfs_setup, Gtfs1, RoadStructure, NULL_ID, WALKING_SPEED};
-use geo_types::{LineString, MultiLineString};
+use crate::{gtfs_setup, RoadStructure, NULL_ID};
+use geo_types::{Coord, LineString, MultiLineString};
 use gtfs_structure_2::gtfs_wrapper::RouteType;
 use gtfs_structure_2::shape::Shape;
 use rstar::PointDistance;
@@ -10,14 +12,31 @@ use std::fmt::{Display, Formatter};
 
 pub struct InProgressTripsFormatter<'a, 'b> {
     pub(crate) trips: Vec<&'a InProgressTrip>,
-    pub(crate) gtfs: &'b Gtfs1,
+    pub(crate) network: Network<'b>,
+    /// Realtime overlay the trips were found with
+    pub(crate) realtime: Option<&'a RealtimeOverlay>,
     pub(crate) final_walking_length: f32,
+    /// Generalized cost of the whole journey, in weighted seconds
+    pub(crate) cost: f64,
 }
 
-fn construct_shape_for_ip_trip(gtfs: &Gtfs1, trip: &InProgressTrip) -> LineString {
-    let gtfs_trip = &gtfs.trips[&trip.trip_id];
+fn construct_shape_for_ip_trip(network: &Network, realtime: Option<&RealtimeOverlay>, trip: &InProgressTrip) -> LineString {
+    // Trips added by realtime feeds have no shape, so go straight from stop to stop
+    let Some(gtfs_trip) = network.find_trip(trip.trip_id) else {
+        let added_trip = crate::realtime::trip(network, realtime, trip.trip_id);
+        return added_trip.stop_times[trip.boarding_stop_time_idx..=trip.get_off_stop_time_idx]
+            .iter()
+            .filter_map(|st| {
+                let stop = network.stop(st.stop_id);
+                Some(Coord {
+                    x: stop.longitude?,
+                    y: stop.latitude?,
+                })
+            })
+            .collect();
+    };
     // let shape = &gtfs.shapes[&gtfs_trip.shape_id.unwrap()];
-    let shape = gtfs.get_shape(gtfs_trip);
+    let shape = network.shape(gtfs_trip);
 
     let boarding_stop_time = &gtfs_trip.stop_times[trip.boarding_stop_time_idx];
     let get_off_stop_time = &gtfs_trip.stop_times[trip.get_off_stop_time_idx];
@@ -36,7 +55,7 @@ impl<'a, 'b> InProgressTripsFormatter<'a, 'b> {
                     if trip.trip_id == NULL_ID {
                         None
                     } else {
-                        let path = construct_shape_for_ip_trip(self.gtfs, trip);
+                        let path = construct_shape_for_ip_trip(&self.network, self.realtime, trip);
 
                         if path.0.len() <= 1 {
                             log::warn!("Invalid path detected: {:?}", trip);
@@ -66,9 +85,9 @@ impl Display for TimeFormatter {
     }
 }
 
-pub fn get_route_mode(gtfs: &Gtfs1, trip: &InProgressTrip) -> &'static str {
+pub fn get_route_mode(network: &Network, trip: &InProgressTrip) -> &'static str {
     let route_id = trip.current_route.route_id;
-    let route = &gtfs.routes[&route_id];
+    let route = network.route(route_id);
     match route.route_type {
         RouteType::Bus => "bus",
         RouteType::Tramway => "tram",
@@ -80,7 +99,7 @@ pub fn get_route_mode(gtfs: &Gtfs1, trip: &InProgressTrip) -> &'static str {
 
 impl<'a, 'b> InProgressTripsFormatter<'a, 'b> {
     pub fn format_in_progress_trip_boarding(
-        gtfs: &Gtfs1,
+        network: &Network,
         trip: &InProgressTrip,
         fmt: &mut Formatter<'_>,
     ) -> std::fmt::Result {
@@ -89,7 +108,7 @@ impl<'a, 'b> InProgressTripsFormatter<'a, 'b> {
         // println!("Trip {:?}", trip);
         // println!("Route id {:?}", trip.current_route);
         let route_id = trip.current_route.route_id;
-        let route = &gtfs.routes[&route_id];
+        let route = network.route(route_id);
         let route_type = match route.route_type {
             RouteType::Bus => "bus",
             RouteType::Tramway => "tram",
@@ -97,7 +116,7 @@ impl<'a, 'b> InProgressTripsFormatter<'a, 'b> {
             _ => "",
         };
         let bus_number = &route.short_name;
-        let stop = &gtfs.stops[&trip.boarding_stop_id];
+        let stop = network.stop(trip.boarding_stop_id);
         fmt.write_fmt(format_args!(
             "Get on {} #{} at {}, {} {:?} {:?}\n",
             route_type,
@@ -112,13 +131,13 @@ impl<'a, 'b> InProgressTripsFormatter<'a, 'b> {
     }
 
     fn format_in_progress_trip_disembark(
-        gtfs: &Gtfs1,
+        network: &Network,
         trip: &InProgressTrip,
         fmt: &mut Formatter<'_>,
     ) -> std::fmt::Result {
         // For disembarking part
-        let _bus_number = &gtfs.routes[&trip.current_route.route_id].short_name;
-        let stop_name = &gtfs.stops[&trip.get_off_stop_id].name;
+        let _bus_number = &network.route(trip.current_route.route_id).short_name;
+        let stop_name = &network.stop(trip.get_off_stop_id).name;
         fmt.write_fmt(format_args!(
             "Get off at {}, {}\n",
             stop_name,
@@ -136,8 +155,8 @@ impl<'a, 'b> Display for InProgressTripsFormatter<'a, 'b> {
                 // Begin of trip. Skip here.
                 continue;
             }
-            InProgressTripsFormatter::format_in_progress_trip_boarding(self.gtfs, trip, f)?;
-            InProgressTripsFormatter::format_in_progress_trip_disembark(self.gtfs, trip, f)?;
+            InProgressTripsFormatter::format_in_progress_trip_boarding(&self.network, trip, f)?;
+            InProgressTripsFormatter::format_in_progress_trip_disembark(&self.network, trip, f)?;
         }
 
         let shape = self.construct_shape();
@@ -148,9 +167,9 @@ impl<'a, 'b> Display for InProgressTripsFormatter<'a, 'b> {
 }
 
 pub fn time_to_point<'a, 'b>(
-    data: &RoadStructure,
+    data: &'a RoadStructure,
     arena: &'a TripsArena,
-    gtfs: &'b Gtfs1,
+    network: Network<'b>,
     point: [f64; 2],
     is_lat_lng: bool,
 ) -> Option<InProgressTripsFormatter<'a, 'b>> {
@@ -160,21 +179,29 @@ pub fn time_to_point<'a, 'b>(
         crate::projection::project_lng_lat(data.city(), point[0], point[1])
     };
 
-    let (_best_time, obs) = data
+    let (cost_to_reach, obs) = data
         .nearest_times_to_point(&point)
         .map(|obs| {
             let distance = obs.distance_2(&point).sqrt();
-            let time_to_reach = obs.data.timestamp + distance / WALKING_SPEED;
-            (time_to_reach, obs)
+            let cost_to_reach =
+                obs.data.cost + distance / data.walking.speed * data.walking.cost_weight;
+            (cost_to_reach, obs)
         })
-        .min_by_key(|(time, obs)| {
+        .min_by_key(|(cost, obs)| {
             // Penalize time for every transfer performed
-            *time + obs.data.transfers as f64 * 120.0
+            *cost + obs.data.transfers as f64 * 120.0
         })?;
 
+    let trips = gtfs_setup::get_trip_transfers(arena, obs.data.progress_trip_id.unwrap());
+    // The first trip of the chain starts at the origin, at the start of the search
+    let search_start = arena
+        .search_mode()
+        .to_search_time(trips.last().unwrap().boarding_time);
     Some(InProgressTripsFormatter {
-        trips: gtfs_setup::get_trip_transfers(arena, obs.data.progress_trip_id.unwrap()),
-        gtfs,
+        trips,
+        network,
+        realtime: data.realtime.as_deref(),
         final_walking_length: obs.data.walking_length as f32,
+        cost: (cost_to_reach - search_start).0,
     })
 }
@@ -1,5 +1,6 @@
 use crate::agencies::City;
 use crate::projection::project_lng_lat;
+use crate::stations::StationWalks;
 use crate::time::Time;
 use crate::web::LatLng;
 use crate::{projection, BusPickupInfo, NULL_ID};
@@ -51,15 +52,11 @@ impl RoutePickupTimes {
             direction: crate::direction_to_bool(&trip.direction_id.unwrap()),
         };
 
-        // TODO: fix this and ignore the bus pickup if time is null
-        if stop_time.arrival_time.is_none() {
-            return;
-        }
-
         let bus_pickup = BusPickupInfo {
-            timestamp: Time(stop_time.arrival_time.unwrap() as f64),
+            timestamp: Time(stop_time.departure_time.unwrap() as f64),
             stop_sequence_no: stop_time.stop_sequence,
             trip_id: trip.id,
+            service_day_offset: 0,
         };
         if let Some(times) = self.0.get_mut(&route_stop_sequence) {
             times.insert(bus_pickup);
@@ -79,10 +76,28 @@ pub struct StopsData {
     pub stop_id: IdType,
 }
 
-#[derive(Debug)]
-pub struct SpatialStopsWithTrips(pub RTree<GeomWithData<[f64; 2], StopsData>>);
+/// Stops by location, along with the walks through stations with pathways
+#[derive(Debug, Default)]
+pub struct SpatialStopsWithTrips(pub RTree<GeomWithData<[f64; 2], StopsData>>, pub StationWalks);
 
 impl SpatialStopsWithTrips {
+    pub fn station_walks(&self) -> &StationWalks {
+        &self.1
+    }
+
+    /// Looks up a stop by ID, through its coordinates
+    pub fn get_stop(
+        &self,
+        city: &City,
+        gtfs: &Gtfs1,
+        stop_id: IdType,
+    ) -> Option<&GeomWithData<[f64; 2], StopsData>> {
+        let point = projection::project_stop(city, gtfs.stops.get(&stop_id)?);
+        self.0
+            .locate_all_at_point(&point)
+            .find(|stop| stop.data.stop_id == stop_id)
+    }
+
     pub fn is_near_point(&self, city: &City, point: LatLng) -> bool {
         let xy = project_lng_lat(city, point.longitude, point.latitude);
         self.0
@@ -116,6 +131,6 @@ impl StopsWithTrips {
             points_data.push(GeomWithData::new(stop_coords, stops_data));
         }
 
-        SpatialStopsWithTrips(RTree::bulk_load(points_data))
+        SpatialStopsWithTrips(RTree::bulk_load(points_data), StationWalks::new(city, gtfs))
     }
 }
@@ -0,0 +1,665 @@
+use crate::agencies::{Agency, City};
+use crate::gtfs_setup::get_agency_id_from_short_name;
+use crate::realtime::{service_day_start, AddedTrip, Alert, RealtimeOverlay};
+use crate::web_app_data::AllAppData;
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use gtfs_structure_2::gtfs_wrapper::{Availability1, DirectionType, Gtfs1, StopTime, Trip};
+use gtfs_structure_2::IdType;
+use prost::Message;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// GTFS-Realtime feeds of TripUpdates and Alerts: one file, or a directory of them. Each file is named after the
+/// short name of its agency, e.g. `city-gtfs/realtime/TTC.pb`.
+const FEEDS_PATH: &str = "city-gtfs/realtime";
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Trips added by the feeds get IDs from here on, away from the IDs of the schedule
+const ADDED_TRIP_IDS: u64 = 1 << 63;
+
+// The subset of gtfs-realtime.proto that is read. See <https://gtfs.org/realtime/proto/>
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    pub header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    pub gtfs_realtime_version: String,
+    /// POSIX time the feed was created
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(bool, optional, tag = "2")]
+    pub is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "5")]
+    pub alert: Option<FeedAlert>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<StopTimeUpdate>,
+    /// Delay of the whole trip, for stops without their own update
+    #[prost(int32, optional, tag = "5")]
+    pub delay: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    /// YYYYMMDD
+    #[prost(string, optional, tag = "3")]
+    pub start_date: Option<String>,
+    #[prost(enumeration = "TripScheduleRelationship", optional, tag = "4")]
+    pub schedule_relationship: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub route_id: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub direction_id: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum TripScheduleRelationship {
+    Scheduled = 0,
+    Added = 1,
+    Unscheduled = 2,
+    Canceled = 3,
+    Replacement = 5,
+    Duplicated = 6,
+    Deleted = 7,
+    New = 8,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    pub stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub arrival: Option<StopTimeEvent>,
+    #[prost(message, optional, tag = "3")]
+    pub departure: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    pub stop_id: Option<String>,
+    #[prost(enumeration = "StopScheduleRelationship", optional, tag = "5")]
+    pub schedule_relationship: Option<i32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum StopScheduleRelationship {
+    Scheduled = 0,
+    Skipped = 1,
+    NoData = 2,
+    Unscheduled = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StopTimeEvent {
+    #[prost(int32, optional, tag = "1")]
+    pub delay: Option<i32>,
+    /// POSIX time
+    #[prost(int64, optional, tag = "2")]
+    pub time: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedAlert {
+    #[prost(message, repeated, tag = "1")]
+    pub active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    pub informed_entity: Vec<EntitySelector>,
+    #[prost(enumeration = "AlertEffect", optional, tag = "7")]
+    pub effect: Option<i32>,
+    #[prost(message, optional, tag = "10")]
+    pub header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    pub description_text: Option<TranslatedString>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeRange {
+    /// POSIX time
+    #[prost(uint64, optional, tag = "1")]
+    pub start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub end: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntitySelector {
+    #[prost(string, optional, tag = "2")]
+    pub route_id: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(string, optional, tag = "5")]
+    pub stop_id: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AlertEffect {
+    NoService = 1,
+    ReducedService = 2,
+    SignificantDelays = 3,
+    Detour = 4,
+    AdditionalService = 5,
+    ModifiedService = 6,
+    OtherEffect = 7,
+    UnknownEffect = 8,
+    StopMoved = 9,
+    NoEffect = 10,
+    AccessibilityIssue = 11,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    pub translation: Vec<Translation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Translation {
+    #[prost(string, required, tag = "1")]
+    pub text: String,
+    #[prost(string, optional, tag = "2")]
+    pub language: Option<String>,
+}
+
+// Seconds after the start of the service day. Absolute times win over delays.
+fn event_time(event: &StopTimeEvent, scheduled: u32, day_start: i64) -> Option<i64> {
+    match (event.time, event.delay) {
+        (Some(time), _) => Some(time - day_start),
+        (None, Some(delay)) => Some(scheduled as i64 + delay as i64),
+        (None, None) => None,
+    }
+}
+
+fn stop_id(gtfs: &Gtfs1, agency_id: u16, update: &StopTimeUpdate) -> Option<IdType> {
+    let stop_id = gtfs.lookup_id(agency_id, update.stop_id.as_deref()?)?;
+    gtfs.stops.contains_key(&stop_id).then_some(stop_id)
+}
+
+fn is_skipped(update: &StopTimeUpdate) -> bool {
+    update.schedule_relationship == Some(StopScheduleRelationship::Skipped as i32)
+}
+
+// Realtime arrival and departure of each stop time of a scheduled trip. An update's delay carries
+// on to the following stops until the next update, as in the GTFS-Realtime spec.
+fn updated_times(
+    gtfs: &Gtfs1,
+    agency_id: u16,
+    trip: &Trip,
+    update: &TripUpdate,
+    day_start: i64,
+) -> Vec<Option<(u32, u32)>> {
+    let shift = |scheduled: u32, delay: Option<i64>| (scheduled as i64 + delay.unwrap_or(0)).max(0) as u32;
+
+    let mut delay = update.delay.map(i64::from);
+    let mut times = Vec::with_capacity(trip.stop_times.len());
+    for st in &trip.stop_times {
+        let arrival = st.arrival_time.unwrap();
+        let departure = st.departure_time.unwrap();
+        let stop_update = update.stop_time_update.iter().find(|stu| match stu.stop_sequence {
+            Some(stop_sequence) => stop_sequence == st.stop_sequence as u32,
+            None => stop_id(gtfs, agency_id, stu) == Some(st.stop_id),
+        });
+
+        let Some(stop_update) = stop_update else {
+            times.push(Some((shift(arrival, delay), shift(departure, delay).max(shift(arrival, delay)))));
+            continue;
+        };
+        if is_skipped(stop_update) {
+            times.push(None);
+            continue;
+        }
+        if stop_update.schedule_relationship == Some(StopScheduleRelationship::NoData as i32) {
+            delay = None;
+            times.push(Some((arrival, departure)));
+            continue;
+        }
+
+        let arrival_delay = stop_update
+            .arrival
+            .as_ref()
+            .and_then(|event| event_time(event, arrival, day_start))
+            .map(|time| time - arrival as i64);
+        let departure_delay = stop_update
+            .departure
+            .as_ref()
+            .and_then(|event| event_time(event, departure, day_start))
+            .map(|time| time - departure as i64);
+        let arrival_delay = arrival_delay.or(departure_delay).or(delay);
+        let departure_delay = departure_delay.or(arrival_delay);
+        delay = departure_delay;
+
+        let updated_arrival = shift(arrival, arrival_delay);
+        times.push(Some((updated_arrival, shift(departure, departure_delay).max(updated_arrival))));
+    }
+    times
+}
+
+// A trip only in the feed, from its stop time updates. Needs a route of the schedule, and a stop
+// and a time for each stop.
+fn added_trip(
+    gtfs: &Gtfs1,
+    agency_id: u16,
+    id: IdType,
+    update: &TripUpdate,
+    day_start: i64,
+) -> Option<Trip> {
+    let route_id = gtfs.lookup_id(agency_id, update.trip.route_id.as_deref()?)?;
+    if !gtfs.routes.contains_key(&route_id) {
+        return None;
+    }
+
+    let mut stop_times = Vec::new();
+    for (index, stop_update) in update.stop_time_update.iter().enumerate() {
+        if is_skipped(stop_update) {
+            continue;
+        }
+        let time = |event: &Option<StopTimeEvent>| {
+            let seconds = event.as_ref()?.time? - day_start;
+            u32::try_from(seconds).ok()
+        };
+        let arrival = time(&stop_update.arrival).or(time(&stop_update.departure))?;
+        let departure = time(&stop_update.departure).unwrap_or(arrival).max(arrival);
+        stop_times.push(StopTime {
+            arrival_time: Some(arrival),
+            departure_time: Some(departure),
+            stop_sequence: stop_update.stop_sequence.map_or(index as u16, |sequence| sequence as u16),
+            stop_id: stop_id(gtfs, agency_id, stop_update)?,
+            trip_id: id,
+            index_of_stop_time: stop_times.len(),
+            shape_index: -1.0,
+            shape_dist_traveled: None,
+        });
+    }
+    if stop_times.len() < 2 {
+        return None;
+    }
+
+    Some(Trip {
+        id,
+        service_id: (agency_id, u64::MAX),
+        route_id,
+        stop_times,
+        shape_id: None,
+        trip_headsign: None,
+        trip_short_name: None,
+        direction_id: Some(match update.trip.direction_id {
+            Some(1) => DirectionType::Inbound,
+            _ => DirectionType::Outbound,
+        }),
+        block_id: None,
+        wheelchair_accessible: Availability1::InformationNotAvailable,
+    })
+}
+
+fn add_trip_update(
+    overlay: &mut RealtimeOverlay,
+    gtfs: &Gtfs1,
+    agency_id: u16,
+    timezone: Tz,
+    feed_date: NaiveDate,
+    update: &TripUpdate,
+) {
+    let descriptor = &update.trip;
+    let start_date = descriptor
+        .start_date
+        .as_deref()
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .unwrap_or(feed_date);
+    let Some(day_start) = service_day_start(timezone, start_date) else {
+        return;
+    };
+    let relationship = descriptor
+        .schedule_relationship
+        .and_then(|relationship| TripScheduleRelationship::try_from(relationship).ok())
+        .unwrap_or(TripScheduleRelationship::Scheduled);
+    let trip_id = descriptor
+        .trip_id
+        .as_deref()
+        .and_then(|trip_id| gtfs.lookup_id(agency_id, trip_id))
+        .filter(|trip_id| gtfs.trips.contains_key(trip_id));
+
+    match (relationship, trip_id) {
+        (TripScheduleRelationship::Scheduled, Some(trip_id)) => {
+            let times = updated_times(gtfs, agency_id, &gtfs.trips[&trip_id], update, day_start);
+            for (st, updated) in gtfs.trips[&trip_id].stop_times.iter().zip(&times) {
+                if let Some((_, departure)) = updated {
+                    let delay = *departure as f64 - st.departure_time.unwrap() as f64;
+                    overlay.max_delay = overlay.max_delay.max(delay);
+                    overlay.max_early = overlay.max_early.max(-delay);
+                }
+            }
+            overlay.updates.insert((trip_id, start_date), times);
+        }
+        (TripScheduleRelationship::Canceled | TripScheduleRelationship::Deleted, Some(trip_id)) => {
+            overlay.canceled.insert((trip_id, start_date));
+        }
+        (TripScheduleRelationship::Added | TripScheduleRelationship::New, _) => {
+            let id = (agency_id, ADDED_TRIP_IDS + overlay.added_trips.len() as u64);
+            let Some(trip) = added_trip(gtfs, agency_id, id, update, day_start) else {
+                log::warn!("Skipping added trip {:?} without a known route, stops or times", descriptor.trip_id);
+                return;
+            };
+            for st in &trip.stop_times {
+                overlay.added_pickups.add_stop(st, &trip);
+            }
+            overlay.added_trips.insert(id, AddedTrip { trip, start_date });
+        }
+        _ => {}
+    }
+}
+
+// The first translation, preferring English
+fn translated_text(text: &TranslatedString) -> Option<&str> {
+    text.translation
+        .iter()
+        .find(|translation| translation.language.as_deref().map_or(false, |language| language.starts_with("en")))
+        .or(text.translation.first())
+        .map(|translation| translation.text.as_str())
+}
+
+// An entity naming a stop and a route affects the stop for that route only, so a closure on one line
+// leaves the other lines of an interchange running. Entities with neither, like whole agencies, are ignored.
+fn add_alert(overlay: &mut RealtimeOverlay, gtfs: &Gtfs1, agency_id: u16, entity_id: &str, alert: &FeedAlert) {
+    let lookup = |id: &Option<String>| gtfs.lookup_id(agency_id, id.as_deref()?);
+    let mut affected = Alert {
+        header: alert
+            .header_text
+            .as_ref()
+            .or(alert.description_text.as_ref())
+            .and_then(translated_text)
+            .unwrap_or(entity_id)
+            .to_string(),
+        active_periods: alert
+            .active_period
+            .iter()
+            .map(|period| (period.start.map(|start| start as i64), period.end.map(|end| end as i64)))
+            .collect(),
+        no_service: alert.effect == Some(AlertEffect::NoService as i32),
+        routes: FxHashSet::default(),
+        stops: FxHashSet::default(),
+        trips: FxHashSet::default(),
+        route_stops: FxHashSet::default(),
+    };
+    for entity in &alert.informed_entity {
+        let route_id = lookup(&entity.route_id).filter(|id| gtfs.routes.contains_key(id));
+        if let Some(stop_id) = lookup(&entity.stop_id).filter(|id| gtfs.stops.contains_key(id)) {
+            match route_id {
+                Some(route_id) => affected.route_stops.insert((route_id, stop_id)),
+                None => affected.stops.insert(stop_id),
+            };
+        } else if let Some(trip_id) = entity
+            .trip
+            .as_ref()
+            .and_then(|trip| lookup(&trip.trip_id))
+            .filter(|id| gtfs.trips.contains_key(id))
+        {
+            affected.trips.insert(trip_id);
+        } else if let Some(route_id) = route_id {
+            affected.routes.insert(route_id);
+        }
+    }
+    if affected.routes.is_empty()
+        && affected.stops.is_empty()
+        && affected.trips.is_empty()
+        && affected.route_stops.is_empty()
+    {
+        return;
+    }
+    overlay.alerts.push(affected);
+}
+
+/// Lays the TripUpdates and Alerts of each agency's feed over the city's schedule
+pub fn build_overlay(
+    gtfs: &Gtfs1,
+    timezone: Tz,
+    feeds: &[(u16, FeedMessage)],
+    version: u64,
+) -> RealtimeOverlay {
+    let mut overlay = RealtimeOverlay {
+        version,
+        ..Default::default()
+    };
+    for (agency_id, feed) in feeds {
+        let feed_time = feed
+            .header
+            .timestamp
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp as i64, 0).single())
+            .unwrap_or_else(Utc::now);
+        let feed_date = feed_time.with_timezone(&timezone).date_naive();
+
+        for entity in &feed.entity {
+            if entity.is_deleted == Some(true) {
+                continue;
+            }
+            if let Some(update) = &entity.trip_update {
+                add_trip_update(&mut overlay, gtfs, *agency_id, timezone, feed_date, update);
+            }
+            if let Some(alert) = &entity.alert {
+                add_alert(&mut overlay, gtfs, *agency_id, &entity.id, alert);
+            }
+        }
+    }
+    overlay
+}
+
+fn feed_files() -> Vec<PathBuf> {
+    let path = Path::new(FEEDS_PATH);
+    if path.is_file() {
+        return vec![path.to_path_buf()];
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+// Feeds of each city, by agency, read from the feed files
+fn read_feeds(files: &[PathBuf], agencies: &[Agency]) -> FxHashMap<City, Vec<(u16, FeedMessage)>> {
+    let mut feeds: FxHashMap<City, Vec<(u16, FeedMessage)>> = FxHashMap::default();
+    for file in files {
+        let Some(short_name) = file.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let agency = agencies.iter().find(|agency| agency.short_code == short_name);
+        let (Some(agency), Some(agency_id)) = (agency, get_agency_id_from_short_name(short_name)) else {
+            log::warn!("No agency named {short_name} for realtime feed {file:?}");
+            continue;
+        };
+        let feed = std::fs::read(file)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| FeedMessage::decode(bytes.as_slice()).map_err(|e| e.to_string()));
+        match feed {
+            Ok(feed) => feeds.entry(agency.city).or_default().push((agency_id, feed)),
+            Err(e) => log::warn!("Invalid realtime feed {file:?}: {e}"),
+        }
+    }
+    feeds
+}
+
+/// Reloads the realtime feeds whenever their files change, replacing the overlay of each city.
+/// Searches already running keep the overlay they started with.
+pub async fn poll_feeds(appdata: Arc<AllAppData>, agencies: Vec<Agency>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_modified = Vec::new();
+    let mut version = 0;
+    loop {
+        interval.tick().await;
+        let files = feed_files();
+        let modified = modified(&files);
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+        version += 1;
+
+        let appdata = appdata.clone();
+        let agencies = agencies.clone();
+        let reload = tokio::task::spawn_blocking(move || {
+            let mut feeds = read_feeds(&files, &agencies);
+            for (city, ad) in &appdata.ads {
+                let city_feeds = feeds.remove(city).unwrap_or_default();
+                let overlay = build_overlay(&ad.gtfs, city.get_timezone(), &city_feeds, version);
+                log::info!(
+                    "Realtime overlay {version} for {city:?}: {} updated, {} canceled and {} added trips, {} alerts",
+                    overlay.updates.len(),
+                    overlay.canceled.len(),
+                    overlay.added_trips.len(),
+                    overlay.alerts.len()
+                );
+                *ad.realtime.write().unwrap() = Arc::new(overlay);
+            }
+        });
+        if let Err(e) = reload.await {
+            log::error!("Reloading realtime feeds failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::Exclusions;
+    use gtfs_structure_2::test_gtfs::{test_trip, TestGtfs};
+
+    // Scheduled every 100 seconds from 0, at stop sequences 0 to 4
+    fn updated_times_of(update: TripUpdate) -> Vec<Option<(u32, u32)>> {
+        let calls: Vec<(u64, Option<u32>)> = (0..5).map(|stop| (stop, Some(stop as u32 * 100))).collect();
+        let gtfs = TestGtfs::default().trip(test_trip(1, 1, &calls)).build();
+        updated_times(&gtfs, 0, &gtfs.trips[&(0, 1)], &update, 0)
+    }
+
+    fn stop_update(stop_sequence: u32, delay: Option<i32>, relationship: StopScheduleRelationship) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_sequence: Some(stop_sequence),
+            arrival: delay.map(|delay| StopTimeEvent {
+                delay: Some(delay),
+                time: None,
+            }),
+            schedule_relationship: Some(relationship as i32),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_delay_propagation() {
+        let times = updated_times_of(TripUpdate {
+            delay: Some(30),
+            stop_time_update: vec![
+                stop_update(2, Some(60), StopScheduleRelationship::Scheduled),
+                // Absolute times are seconds since the Unix epoch, and the service day starts at 0 here
+                StopTimeUpdate {
+                    stop_sequence: Some(3),
+                    departure: Some(StopTimeEvent {
+                        delay: None,
+                        time: Some(350),
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            times,
+            vec![
+                Some((30, 30)),
+                Some((130, 130)),
+                Some((260, 260)),
+                Some((350, 350)),
+                Some((450, 450)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skipped_stops() {
+        let times = updated_times_of(TripUpdate {
+            stop_time_update: vec![
+                stop_update(1, None, StopScheduleRelationship::Skipped),
+                stop_update(3, Some(-20), StopScheduleRelationship::Scheduled),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            times,
+            vec![Some((0, 0)), None, Some((200, 200)), Some((280, 280)), Some((380, 380))]
+        );
+    }
+
+    #[test]
+    fn test_no_data_clears_delay() {
+        let times = updated_times_of(TripUpdate {
+            stop_time_update: vec![
+                stop_update(1, Some(60), StopScheduleRelationship::Scheduled),
+                stop_update(3, None, StopScheduleRelationship::NoData),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            times,
+            vec![Some((0, 0)), Some((160, 160)), Some((260, 260)), Some((300, 300)), Some((400, 400))]
+        );
+    }
+
+    #[test]
+    fn test_route_stop_closures() {
+        let gtfs = TestGtfs::default()
+            .stop(1, Default::default())
+            .stop(2, Default::default())
+            .route(1, Default::default())
+            .build();
+        let entity = |route_id: Option<&str>, stop_id: &str| EntitySelector {
+            route_id: route_id.map(str::to_string),
+            stop_id: Some(stop_id.to_string()),
+            ..Default::default()
+        };
+        let alert = FeedAlert {
+            informed_entity: vec![entity(Some("1"), "1"), entity(None, "2")],
+            effect: Some(AlertEffect::NoService as i32),
+            ..Default::default()
+        };
+        let mut overlay = RealtimeOverlay::default();
+        add_alert(&mut overlay, &gtfs, 0, "closure", &alert);
+
+        let mut exclusions = Exclusions::default();
+        overlay.add_closures(&mut exclusions, 0);
+        // Other routes still serve stop 1
+        assert_eq!(exclusions.route_stops, FxHashSet::from_iter([((0, 1), (0, 1))]));
+        assert_eq!(exclusions.stops, FxHashSet::from_iter([(0, 2)]));
+        assert!(exclusions.routes.is_empty());
+    }
+}
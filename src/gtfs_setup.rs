@@ -11,6 +11,9 @@ use crate::agencies::City;
 use crate::gtfs_processing::StopsWithTrips;
 use crate::in_progress_trip::InProgressTrip;
 use std::sync::Mutex;
+// Bump whenever the archived Gtfs1 layout changes, so stale caches are regenerated instead of misread
+const GTFS_CACHE_VERSION: u32 = 9;
+
 lazy_static! {
     static ref AGENCY_MAP: Mutex<FxHashMap<String, u16>> = Mutex::new(FxHashMap::default());
 }
@@ -20,9 +23,7 @@ pub fn generate_stops_trips(gtfs: &Gtfs1) -> StopsWithTrips {
     let mut result = StopsWithTrips::default();
     for trip in gtfs.trips.values() {
         for st in &trip.stop_times {
-            if st.arrival_time.is_some() {
-                result.add_stop(st, trip);
-            }
+            result.add_stop(st, trip);
         }
     }
     result
@@ -33,17 +34,44 @@ pub fn get_agency_id_from_short_name(short_name: &str) -> Option<u16> {
     map.get(short_name).copied()
 }
 
+/// Makes the agency's ID found by its short name, see [get_agency_id_from_short_name]
+pub(crate) fn register_agency(short_name: &str, agency_id: u16) {
+    let mut map = AGENCY_MAP.lock().unwrap();
+    map.insert(short_name.to_string(), agency_id);
+}
+
 #[test]
 fn test_nj() {
     let _gtfs = initialize_gtfs_as_bson("city-gtfs/nj-bus", City::NewYorkCity);
 }
 pub fn initialize_gtfs_as_bson(path: &str, city: City) -> Vec<Gtfs1> {
+    let result = load_gtfs(path, city);
+
+    for agency in &result {
+        let short_name = &agency.agency_name;
+
+        let sample_id = agency.stops.keys().next().unwrap();
+        register_agency(short_name, sample_id.0);
+        println!(
+            "Agency {}: {} {}",
+            sample_id.0,
+            agency.agency_name,
+            agency.generated_shapes.len()
+        );
+    }
+    result
+}
+
+/// Schedules of each agency of the feed, from its cache or else parsed and cached. Unlike
+/// [initialize_gtfs_as_bson], the agencies aren't looked up by short name afterwards, so other
+/// versions of a feed don't replace the current one.
+pub fn load_gtfs(path: &str, city: City) -> Vec<Gtfs1> {
     info!("Loading schedules for {path}");
-    let file = File::create_new(format!("{path}-1.rkyv"));
+    let file = File::create_new(format!("{path}-{GTFS_CACHE_VERSION}.rkyv"));
 
     let result: Vec<Gtfs1> = if let Ok(mut file) = file {
         // if cfg!(feature = "prod") {
-        //     panic!("Prod deployment -- not allowed to parse GTFS txt files. Not found {path}-1.rkyv file");
+        //     panic!("Prod deployment -- not allowed to parse GTFS txt files. Not found {path}-{GTFS_CACHE_VERSION}.rkyv file");
         // }
         info!("GTFS not detected! Creating new {}", path);
 
@@ -63,7 +91,7 @@ pub fn initialize_gtfs_as_bson(path: &str, city: City) -> Vec<Gtfs1> {
         info!("GTFS created");
         gtfslist
     } else {
-        let mut file = File::open(format!("{path}-1.rkyv")).unwrap();
+        let mut file = File::open(format!("{path}-{GTFS_CACHE_VERSION}.rkyv")).unwrap();
         let mut bytes = Vec::new();
         file.read_to_end(&mut bytes).unwrap();
 
@@ -73,20 +101,6 @@ pub fn initialize_gtfs_as_bson(path: &str, city: City) -> Vec<Gtfs1> {
             unsafe { rkyv::from_bytes_unchecked(&bytes) }.unwrap()
         }
     };
-
-    for agency in &result {
-        let short_name = &agency.agency_name;
-
-        let sample_id = agency.stops.keys().next().unwrap();
-        let mut map = AGENCY_MAP.lock().unwrap();
-        map.insert(short_name.to_string(), sample_id.0);
-        println!(
-            "Agency {}: {} {}",
-            sample_id.0,
-            agency.agency_name,
-            agency.generated_shapes.len()
-        );
-    }
     result
 }
 
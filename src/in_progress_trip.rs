@@ -1,7 +1,11 @@
+use crate::configuration::SearchMode;
+use crate::fares::FareProgress;
 use crate::gtfs_processing::RouteStopSequence;
 use crate::time::Time;
+use crate::NULL_ID;
 use gtfs_structure_2::IdType;
 use id_arena::Id;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct InProgressTrip {
@@ -19,4 +23,58 @@ pub struct InProgressTrip {
     pub walking_length_m: f32,
     pub boarding_stop_time_idx: usize,
     pub get_off_stop_time_idx: usize,
+    // Service day of the trip relative to the requested service date. Times above are already shifted.
+    pub service_day_offset: i8,
+    // Index of the origin (or destination, for arrive-by) this trip started from
+    pub origin: u8,
+    // Generalized cost from the origin to the frontier, in weighted seconds
+    pub cost: f64,
+    // Fare so far, only tracked when searching with a max fare
+    pub fare: Option<Arc<FareProgress>>,
+}
+
+impl InProgressTrip {
+    /// The stop and clock time the search continues exploring from. That is where we get off
+    /// when departing at a time, and where we board when arriving by a time.
+    pub fn frontier(&self, mode: SearchMode) -> (IdType, Time) {
+        match mode {
+            SearchMode::DepartAt => (self.get_off_stop_id, self.exit_time),
+            SearchMode::ArriveBy => (self.boarding_stop_id, self.boarding_time),
+        }
+    }
+
+    /// Stops cycled to from the origin are trips without a vehicle that get off at the stop
+    pub fn is_bike_access(&self) -> bool {
+        self.trip_id == NULL_ID && self.get_off_stop_id != NULL_ID
+    }
+}
+
+#[cfg(test)]
+impl InProgressTrip {
+    /// Riding trip (0, 1) of route (0, 1) between two stops, as the first vehicle of the journey
+    pub fn test_ride(boarding_stop_id: IdType, get_off_stop_id: IdType, boarding_time: f64, exit_time: f64) -> Self {
+        InProgressTrip {
+            trip_id: (0, 1),
+            boarding_time: Time(boarding_time),
+            exit_time: Time(exit_time),
+            point: [0.0, 0.0],
+            current_route: RouteStopSequence {
+                route_id: (0, 1),
+                ..Default::default()
+            },
+            total_transfers: 1,
+            get_off_stop_id,
+            boarding_stop_id,
+            previous_transfer: None,
+            is_free_transfer: false,
+            walking_time: Time(0.0),
+            walking_length_m: 0.0,
+            boarding_stop_time_idx: 0,
+            get_off_stop_time_idx: 0,
+            service_day_offset: 0,
+            origin: 0,
+            cost: 0.0,
+            fare: None,
+        }
+    }
 }
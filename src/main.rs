@@ -4,18 +4,32 @@
 #![feature(vec_into_raw_parts)]
 
 use anyhow::Result;
+use chrono::Utc;
 mod agencies;
 mod best_times;
+mod bike_access;
+mod commute;
 mod configuration;
+mod fares;
+mod feed_diff;
+#[cfg(feature = "realtime")]
+mod gtfs_rt;
 mod formatter;
 mod gtfs_processing;
 mod gtfs_setup;
 mod in_progress_trip;
+mod network;
 mod path_usage;
+mod profile;
 mod projection;
+mod raptor;
 mod reach_data;
+mod realtime;
 mod road_structure;
+mod scenario;
 mod serialization;
+mod stations;
+mod street_modes;
 mod time;
 mod time_to_reach;
 mod trip_details;
@@ -38,12 +52,16 @@ use tokio::runtime;
 
 use crate::road_structure::RoadStructure;
 use crate::web::LatLng;
-use configuration::Configuration;
+use configuration::{
+    Configuration, CostModel, SearchEngine, SearchMode, TravelMode, WalkingParameters,
+    DEFAULT_MAX_TRANSFERS,
+};
 use gtfs_structure_2::gtfs_wrapper::Gtfs1;
 
 use crate::agencies::{Agency, City};
 use crate::formatter::time_to_point;
 use crate::gtfs_setup::get_agency_id_from_short_name;
+use crate::network::Network;
 use time::Time;
 use trips_arena::TripsArena;
 
@@ -51,6 +69,7 @@ const WALKING_SPEED: f64 = 1.42;
 const STRAIGHT_WALKING_SPEED: f64 = 1.25;
 pub const MIN_TRANSFER_SECONDS: f64 = 35.0;
 pub const TRANSIT_EXIT_PENALTY: f64 = 10.0;
+pub const SECONDS_PER_DAY: f64 = 24.0 * 3600.0;
 const NULL_ID: (u16, u64) = (u16::MAX, u64::MAX);
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone)]
@@ -58,6 +77,22 @@ pub struct BusPickupInfo {
     timestamp: Time,
     stop_sequence_no: u16,
     trip_id: IdType,
+    // Service day this pickup belongs to, relative to the requested service date.
+    // Pickups stored in RoutePickupTimes are always relative to their own service day (0).
+    service_day_offset: i8,
+}
+
+impl BusPickupInfo {
+    /// Moves a pickup from its own service day onto the timeline of the requested service date.
+    /// A trip at 24:45 from yesterday becomes 00:45, a trip at 00:20 tomorrow becomes 24:20.
+    pub fn shifted_by_days(&self, day_offset: i8) -> BusPickupInfo {
+        BusPickupInfo {
+            timestamp: self.timestamp + day_offset as f64 * SECONDS_PER_DAY,
+            stop_sequence_no: self.stop_sequence_no,
+            trip_id: self.trip_id,
+            service_day_offset: day_offset,
+        }
+    }
 }
 
 fn direction_to_bool(d: &DirectionType) -> bool {
@@ -75,23 +110,40 @@ fn main1() {
         .map(|a| get_agency_id_from_short_name(&a.public_name).unwrap())
         .collect();
     let data = gtfs_setup::generate_stops_trips(&gtfs).into_spatial(&City::Paris, &gtfs);
+    let network = Network::new(&gtfs, &data);
 
     let mut rs = RoadStructure::new_city(City::Paris);
     let time = Instant::now();
     for _ in 0..20 {
         rs.clear_data();
         time_to_reach::generate_reach_times(
-            &gtfs,
-            &data,
+            &network,
             &mut rs,
             Configuration {
                 // start_time: Time(3600.0 * 13.0),
                 start_time: Time(3600.0 * 17.0 + 60.0 * 20.0),
                 duration_secs: 3600.0 * 2.0,
                 location: LatLng::from_lat_lng(48.860679403040606, 2.3423617371568994),
+                extra_origins: vec![],
                 agency_ids: agency_ids.clone(),
                 modes: vec![],
-                transfer_cost: 0
+                transfer_cost: 0,
+                service_date: Utc::now().with_timezone(&City::Paris.get_timezone()).date_naive(),
+                timezone: City::Paris.get_timezone(),
+                search_mode: SearchMode::DepartAt,
+                profile_window_secs: None,
+                wheelchair: false,
+                walking: WalkingParameters::default(),
+                max_transfers: DEFAULT_MAX_TRANSFERS,
+                pareto_transfers: false,
+                engine: SearchEngine::Heuristic,
+                travel_mode: TravelMode::Transit,
+                cost: CostModel::default(),
+                max_fare: None,
+                exclusions: Default::default(),
+                realtime: None,
+                alerts_time: 0,
+                bike_access: vec![],
             },
         );
         let et = rs.save();
@@ -105,7 +157,7 @@ fn main1() {
         let _fmter = time_to_point(
             &rs,
             &rs.trips_arena,
-            &gtfs,
+            network,
             [48.836143932204806, 2.240355829094007],
             true,
         );
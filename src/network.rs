@@ -0,0 +1,170 @@
+use crate::agencies::City;
+use crate::gtfs_processing::{SpatialStopsWithTrips, StopsData};
+use crate::projection;
+use crate::scenario::Scenario;
+use crate::stations::StationWalks;
+use chrono::NaiveDate;
+use gtfs_structure_2::calendar::Calendar;
+use gtfs_structure_2::fares::Fares;
+use gtfs_structure_2::gtfs_wrapper::{AgencyId, Gtfs1, Route, Stop, Trip};
+use gtfs_structure_2::shape::Shape;
+use gtfs_structure_2::IdType;
+use rstar::primitives::GeomWithData;
+
+pub type SpatialStop = GeomWithData<[f64; 2], StopsData>;
+
+/// The network searched: the city's schedule and stop index, seen through the changes of a
+/// scenario when there is one. The city's own are shared by every scenario and never changed.
+/// Transfers and pathways are always the city's.
+#[derive(Clone, Copy)]
+pub struct Network<'a> {
+    pub gtfs: &'a Gtfs1,
+    pub spatial: &'a SpatialStopsWithTrips,
+    pub scenario: Option<&'a Scenario>,
+}
+
+impl<'a> Network<'a> {
+    /// The city's network without any scenario
+    pub fn new(gtfs: &'a Gtfs1, spatial: &'a SpatialStopsWithTrips) -> Self {
+        Network {
+            gtfs,
+            spatial,
+            scenario: None,
+        }
+    }
+
+    pub fn find_stop(&self, stop_id: IdType) -> Option<&'a Stop> {
+        self.scenario
+            .and_then(|scenario| scenario.stops.get(&stop_id))
+            .or_else(|| self.gtfs.stops.get(&stop_id))
+    }
+
+    pub fn stop(&self, stop_id: IdType) -> &'a Stop {
+        self.find_stop(stop_id).unwrap()
+    }
+
+    pub fn route(&self, route_id: IdType) -> &'a Route {
+        self.scenario
+            .and_then(|scenario| scenario.routes.get(&route_id))
+            .unwrap_or_else(|| &self.gtfs.routes[&route_id])
+    }
+
+    /// Trips removed by the scenario are still found, for the IDs of requests
+    pub fn find_trip(&self, trip_id: IdType) -> Option<&'a Trip> {
+        self.scenario
+            .and_then(|scenario| scenario.trips.get(&trip_id))
+            .or_else(|| self.gtfs.trips.get(&trip_id))
+    }
+
+    pub fn trip(&self, trip_id: IdType) -> &'a Trip {
+        self.find_trip(trip_id).unwrap()
+    }
+
+    /// Stops of the network, some of them twice when the scenario replaces them
+    pub fn stops(&self) -> impl Iterator<Item = &'a Stop> {
+        self.gtfs
+            .stops
+            .values()
+            .chain(self.scenario.into_iter().flat_map(|scenario| scenario.stops.values()))
+    }
+
+    // Calendar holding the service. Services the scenario has take precedence over the city's.
+    fn calendar(&self, service_id: IdType) -> &'a Calendar {
+        match self.scenario {
+            Some(scenario)
+                if scenario.calendar.services.contains_key(&service_id)
+                    || scenario.calendar.exceptions.contains_key(&service_id) =>
+            {
+                &scenario.calendar
+            }
+            _ => &self.gtfs.calendar,
+        }
+    }
+
+    pub fn runs_on_date(&self, service_id: IdType, date: NaiveDate) -> bool {
+        self.calendar(service_id).runs_on_date(service_id, date)
+    }
+
+    pub fn shape(&self, trip: &Trip) -> &'a Vec<Shape> {
+        let shape = self
+            .scenario
+            .and_then(|scenario| scenario.shapes.get(&trip.shape_id?));
+        shape.unwrap_or_else(|| self.gtfs.get_shape(trip))
+    }
+
+    /// Fare data of the agency, from its other feed version when the scenario has one
+    pub fn fares(&self, agency_id: AgencyId) -> Option<&'a Fares> {
+        match self.scenario.and_then(|scenario| scenario.versions.get(&agency_id)) {
+            Some(version) => version.fares.as_ref(),
+            None => self.gtfs.fares.get(&agency_id),
+        }
+    }
+
+    /// ID of a stop, route or trip from the ID it has in the agency's feed, see [Gtfs1::lookup_id]
+    pub fn lookup_id(&self, agency_id: AgencyId, id: &str) -> Option<IdType> {
+        let Some(version) = self.scenario.and_then(|scenario| scenario.versions.get(&agency_id)) else {
+            return self.gtfs.lookup_id(agency_id, id);
+        };
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => *version.gtfs_ids.get(id)?,
+        };
+        Some((agency_id, id))
+    }
+
+    pub fn station_walks(&self) -> &'a StationWalks {
+        self.spatial.station_walks()
+    }
+
+    // The stop of the city's index as the scenario changes it. None when the scenario indexes the
+    // stop itself, at its own location.
+    fn changed(&self, stop: &'a SpatialStop) -> Option<&'a SpatialStop> {
+        let Some(scenario) = self.scenario else {
+            return Some(stop);
+        };
+        if let Some(changed) = scenario.changed_stops.get(&stop.data.stop_id) {
+            return Some(changed);
+        }
+        (!scenario.stops.contains_key(&stop.data.stop_id)).then_some(stop)
+    }
+
+    /// Stops with pickups within `max_distance` meters of the point, nearest first, with their
+    /// squared distance
+    pub fn stops_near(&self, point: &[f64; 2], max_distance: f64) -> Vec<(&'a SpatialStop, f64)> {
+        let max_distance_2 = max_distance * max_distance;
+        let mut stops: Vec<(&SpatialStop, f64)> = self
+            .spatial
+            .0
+            .nearest_neighbor_iter_with_distance_2(point)
+            .take_while(|(_, distance_2)| *distance_2 <= max_distance_2)
+            .filter_map(|(stop, distance_2)| Some((self.changed(stop)?, distance_2)))
+            .collect();
+        if let Some(scenario) = self.scenario {
+            stops.extend(
+                scenario
+                    .added_stops
+                    .nearest_neighbor_iter_with_distance_2(point)
+                    .take_while(|(_, distance_2)| *distance_2 <= max_distance_2),
+            );
+            stops.sort_by(|a, b| a.1.total_cmp(&b.1));
+        }
+        stops
+    }
+
+    /// Looks up a stop with pickups by ID, see [SpatialStopsWithTrips::get_stop]
+    pub fn get_stop(&self, city: &City, stop_id: IdType) -> Option<&'a SpatialStop> {
+        let Some(scenario) = self.scenario else {
+            return self.spatial.get_stop(city, self.gtfs, stop_id);
+        };
+        if let Some(changed) = scenario.changed_stops.get(&stop_id) {
+            return Some(changed);
+        }
+        match scenario.stops.get(&stop_id) {
+            Some(stop) => scenario
+                .added_stops
+                .locate_all_at_point(&projection::project_stop(city, stop))
+                .find(|stop| stop.data.stop_id == stop_id),
+            None => self.spatial.get_stop(city, self.gtfs, stop_id),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::configuration::Configuration;
+use crate::network::Network;
+use crate::road_structure::{EdgeId, RoadStructure};
+use crate::time::Time;
+use crate::time_to_reach;
+use rustc_hash::FxHashMap;
+use serde::ser::SerializeTuple;
+use serde::{Serialize, Serializer};
+
+pub const PROFILE_STEP_SECS: f64 = 60.0;
+
+/// Travel time statistics of one edge over every departure in the window.
+/// Departures that can't reach the edge count as infinitely long, so the median and 90th percentile
+/// are only defined if enough departures reach the edge.
+#[derive(Debug)]
+pub struct EdgeProfile {
+    pub edge_id: EdgeId,
+    pub min: f64,
+    pub median: Option<f64>,
+    pub p90: Option<f64>,
+}
+
+impl Serialize for EdgeProfile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(3)?;
+        tuple.serialize_element(&(self.min as u32))?;
+        tuple.serialize_element(&self.median.map(|a| a as u32))?;
+        tuple.serialize_element(&self.p90.map(|a| a as u32))?;
+        tuple.end()
+    }
+}
+
+// Nearest-rank percentile. `sorted` only holds the departures that reached the edge.
+fn percentile(sorted: &[f32], total_departures: usize, p: f64) -> Option<f64> {
+    let rank = ((p * total_departures as f64).ceil() as usize).max(1);
+    sorted.get(rank - 1).map(|a| *a as f64)
+}
+
+/// Runs the search for every departure minute in `config.profile_window_secs`, starting at
+/// `config.start_time`.
+///
+/// Departures are searched from the latest to the earliest while reusing the same RoadStructure. Any
+/// journey of a later departure can also be made by leaving earlier and waiting, so the best times
+/// found so far prune most of the work of the next search. After returning, `rs` holds the result
+/// of departing at `config.start_time`.
+pub fn generate_profile(
+    network: &Network,
+    rs: &mut RoadStructure,
+    config: Configuration,
+) -> Vec<EdgeProfile> {
+    let window = config.profile_window_secs.unwrap_or(0.0);
+    let search_mode = config.search_mode;
+    let mut departures: Vec<Time> = (0..=(window / PROFILE_STEP_SECS) as u32)
+        .map(|i| config.start_time + i as f64 * PROFILE_STEP_SECS)
+        .collect();
+
+    // Later search starts first. For arrive-by, that is the earliest deadline.
+    departures.sort_by_key(|departure| std::cmp::Reverse(search_mode.to_search_time(*departure)));
+
+    let mut travel_times: FxHashMap<EdgeId, Vec<f32>> = FxHashMap::default();
+    for departure in &departures {
+        let search_start = search_mode.to_search_time(*departure);
+        time_to_reach::generate_reach_times(
+            network,
+            rs,
+            Configuration {
+                start_time: *departure,
+                ..config.clone()
+            },
+        );
+
+        for edge_time in rs.save() {
+            let travel_time = search_mode.to_search_time(Time(edge_time.time)) - search_start;
+            // Times reused from later departures can be out of this departure's search window
+            if travel_time.0 <= config.duration_secs {
+                travel_times
+                    .entry(edge_time.edge_id)
+                    .or_default()
+                    .push(travel_time.0 as f32);
+            }
+        }
+    }
+
+    travel_times
+        .into_iter()
+        .map(|(edge_id, mut times)| {
+            times.sort_by(f32::total_cmp);
+            EdgeProfile {
+                edge_id,
+                min: times[0] as f64,
+                median: percentile(&times, departures.len(), 0.5),
+                p90: percentile(&times, departures.len(), 0.9),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,375 @@
+use crate::agencies::City;
+use crate::configuration::{Configuration, SearchMode};
+use crate::gtfs_processing::{RouteStopSequence, StopsData};
+use crate::in_progress_trip::InProgressTrip;
+use crate::network::Network;
+use crate::reach_data::ReachData;
+use crate::realtime::{self, TripTimes};
+use crate::road_structure::RoadStructure;
+use crate::time::Time;
+use crate::time_to_reach::{
+    bike_access_trips, earliest_pickup, get_stop_from_stop_seq_no, is_route_allowed,
+    is_route_stop_allowed, is_stop_allowed, origin_trip, service_dates, shifted_service_date,
+    transfer_candidates,
+};
+use crate::stations::{Direction, StationAccess};
+use crate::{projection, BusPickupInfo, SECONDS_PER_DAY, TRANSIT_EXIT_PENALTY};
+use chrono::NaiveDate;
+use gtfs_structure_2::gtfs_wrapper::StopTime;
+use gtfs_structure_2::IdType;
+use id_arena::Id;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+// Earliest time a vehicle can be boarded at a stop, and the trip we walked over from
+#[derive(Clone)]
+struct Boardable {
+    time: Time,
+    previous_transfer: Id<InProgressTrip>,
+    walking_time: Time,
+    walking_length: f64,
+    origin: u8,
+}
+
+// Earliest arrival search in rounds (RAPTOR). Round k finds the earliest arrival at every stop using
+// exactly k vehicles, so unlike the heuristic search no journey is pruned by a worse one. Trips are
+// stored in the same TripsArena tree and reach times in the same BestTimes, so trip details work
+// for both engines. Costs are plain travel times. Staying on the vehicle onto the next trip of its
+// block is the same vehicle, so it's ridden within the round like in the heuristic search.
+pub fn generate_reach_times(network: &Network, rs: &mut RoadStructure, config: Configuration) {
+    assert_eq!(config.search_mode, SearchMode::DepartAt);
+    let city = *rs.city();
+    rs.trips_arena.set_search_mode(SearchMode::DepartAt);
+    rs.walking = config.walking;
+    rs.set_pareto(config.pareto_transfers);
+    rs.bike_access = config.bike_access.clone();
+    rs.realtime = config.realtime.clone();
+    rs.service_date = config.service_date;
+    rs.alerts_time = config.alerts_time;
+
+    let search_end = config.start_time + config.duration_secs;
+    let service_dates = service_dates(&config);
+
+    // StopID -> Earliest arrival over all rounds so far
+    let mut best_arrivals: FxHashMap<IdType, Time> = FxHashMap::default();
+    let mut boardable: FxHashMap<IdType, Boardable> = FxHashMap::default();
+    let mut marked = FxHashMap::default();
+    for (origin_idx, location) in config.origins().enumerate() {
+        let origin = origin_trip(&city, &config, origin_idx, location);
+        let origin_id = rs.trips_arena.alloc(origin.clone());
+        let bike_trips = bike_access_trips(&city, network, &config, &origin, origin_id);
+        let mut starts = vec![(origin, origin_id)];
+        for trip in bike_trips {
+            let id = rs.trips_arena.alloc(trip.clone());
+            starts.push((trip, id));
+        }
+
+        for (trip, id) in starts {
+            rs.add_observation(
+                &trip.point,
+                ReachData {
+                    timestamp: trip.exit_time + TRANSIT_EXIT_PENALTY,
+                    cost: trip.exit_time + TRANSIT_EXIT_PENALTY,
+                    progress_trip_id: Some(id),
+                    transfers: 0,
+                    walking_length: 0.0,
+                    origin: origin_idx as u8,
+                },
+            );
+            marked.extend(relax_transfers(
+                &city,
+                network,
+                &trip,
+                id,
+                &config,
+                &mut boardable,
+            ));
+        }
+    }
+
+    for round in 1..=config.max_transfers.saturating_add(1) {
+        if marked.is_empty() {
+            break;
+        }
+
+        // StopID -> trip arriving there earliest in this round
+        let mut improved: FxHashMap<IdType, Id<InProgressTrip>> = FxHashMap::default();
+        for stop_d in marked.values() {
+            let from = boardable[&stop_d.stop_id].clone();
+            for (route_info, route_pickup, added_pickup) in
+                realtime::route_pickups(stop_d, config.realtime.as_deref())
+            {
+                if !is_route_allowed(network, route_info, &config)
+                    || !is_route_stop_allowed(route_info, network.stop(stop_d.stop_id), &config)
+                {
+                    continue;
+                }
+
+                let pickup = earliest_pickup(
+                    network,
+                    route_pickup,
+                    added_pickup,
+                    from.time,
+                    search_end,
+                    &service_dates,
+                    &config,
+                    |_| true,
+                );
+                if let Some(pickup) = pickup {
+                    scan_trip(
+                        &city,
+                        network,
+                        rs,
+                        pickup,
+                        route_info,
+                        from.clone(),
+                        round,
+                        search_end,
+                        &service_dates,
+                        &config,
+                        &mut best_arrivals,
+                        &mut improved,
+                    );
+                }
+            }
+        }
+
+        marked = FxHashMap::default();
+        for (_, trip_id) in improved {
+            let trip = rs.trips_arena.get_by_id(trip_id).clone();
+            let exit = StationAccess::new(
+                &city,
+                network.gtfs,
+                network.station_walks(),
+                trip.get_off_stop_id,
+                trip.point,
+                Direction::Outward,
+                &config,
+            );
+            for (point, secs) in exit.street_points {
+                rs.add_observation(
+                    &point,
+                    ReachData {
+                        timestamp: trip.exit_time + TRANSIT_EXIT_PENALTY + secs,
+                        cost: trip.exit_time + TRANSIT_EXIT_PENALTY + secs,
+                        progress_trip_id: Some(trip_id),
+                        transfers: round,
+                        walking_length: 0.0,
+                        origin: trip.origin,
+                    },
+                );
+            }
+            marked.extend(relax_transfers(
+                &city,
+                network,
+                &trip,
+                trip_id,
+                &config,
+                &mut boardable,
+            ));
+        }
+    }
+}
+
+// Rides the trip of `pickup` from its boarding stop, and on through the next trips of its block,
+// recording stops reached earlier than before
+#[allow(clippy::too_many_arguments)]
+fn scan_trip(
+    city: &City,
+    network: &Network,
+    rs: &mut RoadStructure,
+    mut pickup: BusPickupInfo,
+    route_info: &RouteStopSequence,
+    mut from: Boardable,
+    round: u8,
+    search_end: Time,
+    service_dates: &[NaiveDate; 3],
+    config: &Configuration,
+    best_arrivals: &mut FxHashMap<IdType, Time>,
+    improved: &mut FxHashMap<IdType, Id<InProgressTrip>>,
+) {
+    let realtime = config.realtime.as_deref();
+    let mut route_info = route_info.clone();
+    let mut is_free_transfer = false;
+    // Against blocks that loop back onto a trip already ridden
+    let mut ridden = FxHashSet::default();
+    while ridden.insert(pickup.trip_id) {
+        let day_shift = pickup.service_day_offset as f64 * SECONDS_PER_DAY;
+        let stop_times = &realtime::trip(network, realtime, pickup.trip_id).stop_times;
+        let times = TripTimes::new(
+            realtime,
+            pickup.trip_id,
+            shifted_service_date(config.service_date, pickup.service_day_offset),
+        );
+        let (boarding_stop, stop_time_index) =
+            get_stop_from_stop_seq_no(stop_times, pickup.stop_sequence_no);
+        let ride = |st: &StopTime, stop, exit_time| InProgressTrip {
+            trip_id: pickup.trip_id,
+            boarding_time: pickup.timestamp,
+            exit_time,
+            point: projection::project_stop(city, stop),
+            current_route: route_info.clone(),
+            get_off_stop_id: st.stop_id,
+            boarding_stop_id: boarding_stop.stop_id,
+            total_transfers: round,
+            previous_transfer: Some(from.previous_transfer),
+            is_free_transfer,
+            walking_time: from.walking_time,
+            walking_length_m: from.walking_length as f32,
+            boarding_stop_time_idx: boarding_stop.index_of_stop_time,
+            get_off_stop_time_idx: st.index_of_stop_time,
+            service_day_offset: pickup.service_day_offset,
+            origin: from.origin,
+            cost: (exit_time - config.start_time).0,
+            fare: None,
+        };
+
+        // Last stop reached, and the trip getting off there if it was recorded
+        let mut last = None;
+        for st in &stop_times[stop_time_index + 1..] {
+            // Skipped by the realtime feed
+            let Some(arrival_time) = times.arrival(st) else {
+                continue;
+            };
+            let arrival_time = Time(arrival_time as f64 + day_shift);
+            if arrival_time > search_end {
+                break;
+            }
+            last = Some((st, arrival_time, None));
+            if best_arrivals
+                .get(&st.stop_id)
+                .is_some_and(|best| *best <= arrival_time)
+            {
+                continue;
+            }
+
+            let stop = network.stop(st.stop_id);
+            if !is_stop_allowed(stop, config) || !is_route_stop_allowed(&route_info, stop, config) {
+                continue;
+            }
+            best_arrivals.insert(st.stop_id, arrival_time);
+
+            let id = rs.trips_arena.alloc(ride(st, stop, arrival_time));
+            improved.insert(st.stop_id, id);
+            last = Some((st, arrival_time, Some(id)));
+        }
+
+        let Some((last_st, arrival_time, last_id)) = last else {
+            return;
+        };
+        // Only the trip's own last stop is where the vehicle goes on
+        if last_st.index_of_stop_time != stop_times.last().unwrap().index_of_stop_time {
+            return;
+        }
+        let next = block_continuation(
+            city,
+            network,
+            &pickup,
+            last_st.stop_id,
+            arrival_time,
+            search_end,
+            service_dates,
+            config,
+        );
+        let Some((next_pickup, next_route)) = next else {
+            return;
+        };
+        let previous_transfer = match last_id {
+            Some(id) => id,
+            None => {
+                let stop = network.stop(last_st.stop_id);
+                rs.trips_arena.alloc(ride(last_st, stop, arrival_time))
+            }
+        };
+        from = Boardable {
+            time: arrival_time,
+            previous_transfer,
+            walking_time: Time(0.0),
+            walking_length: 0.0,
+            origin: from.origin,
+        };
+        pickup = next_pickup;
+        route_info = next_route;
+        is_free_transfer = true;
+    }
+}
+
+// Earliest pickup at `stop_id` of the next trip of the same block as `pickup`, on the same service day
+#[allow(clippy::too_many_arguments)]
+fn block_continuation(
+    city: &City,
+    network: &Network,
+    pickup: &BusPickupInfo,
+    stop_id: IdType,
+    arrival_time: Time,
+    search_end: Time,
+    service_dates: &[NaiveDate; 3],
+    config: &Configuration,
+) -> Option<(BusPickupInfo, RouteStopSequence)> {
+    let realtime = config.realtime.as_deref();
+    let block_id = realtime::trip(network, realtime, pickup.trip_id).block_id.as_ref()?;
+    let stop = network.get_stop(city, stop_id)?;
+
+    let mut continuation: Option<(BusPickupInfo, RouteStopSequence)> = None;
+    for (route_info, route_pickup, added_pickup) in realtime::route_pickups(&stop.data, realtime) {
+        if !is_route_allowed(network, route_info, config)
+            || !is_route_stop_allowed(route_info, network.stop(stop_id), config)
+        {
+            continue;
+        }
+        let next = earliest_pickup(
+            network,
+            route_pickup,
+            added_pickup,
+            arrival_time,
+            search_end,
+            service_dates,
+            config,
+            |bus| {
+                bus.trip_id != pickup.trip_id
+                    && bus.service_day_offset == pickup.service_day_offset
+                    && realtime::trip(network, realtime, bus.trip_id).block_id.as_ref() == Some(block_id)
+            },
+        );
+        if let Some(next) = next {
+            if continuation.as_ref().map_or(true, |(best, _)| next.timestamp < best.timestamp) {
+                continuation = Some((next, route_info.clone()));
+            }
+        }
+    }
+    continuation
+}
+
+// Walks from where `trip` gets off to nearby stops. Returns the stops that can now be boarded earlier.
+fn relax_transfers<'a>(
+    city: &City,
+    network: &Network<'a>,
+    trip: &InProgressTrip,
+    trip_id: Id<InProgressTrip>,
+    config: &Configuration,
+    boardable: &mut FxHashMap<IdType, Boardable>,
+) -> FxHashMap<IdType, &'a StopsData> {
+    let mut marked = FxHashMap::default();
+    for candidate in transfer_candidates(city, network, trip, SearchMode::DepartAt, config) {
+        let stop_id = candidate.stop.stop_id;
+        if !is_stop_allowed(network.stop(stop_id), config) {
+            continue;
+        }
+
+        let time = trip.exit_time + candidate.transfer_time;
+        if boardable.get(&stop_id).is_some_and(|b| b.time <= time) {
+            continue;
+        }
+        boardable.insert(
+            stop_id,
+            Boardable {
+                time,
+                previous_transfer: trip_id,
+                walking_time: Time(candidate.walking_time),
+                walking_length: candidate.walking_length,
+                origin: trip.origin,
+            },
+        );
+        marked.insert(stop_id, candidate.stop);
+    }
+    marked
+}
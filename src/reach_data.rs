@@ -5,18 +5,25 @@ use id_arena::Id;
 #[derive(PartialOrd, PartialEq, Debug, Clone)]
 pub struct ReachData {
     pub timestamp: Time,
+    /// Generalized cost on the search timeline, equal to `timestamp` with the default cost model.
+    /// Best times keep the lowest cost.
+    pub cost: Time,
     pub progress_trip_id: Option<Id<InProgressTrip>>,
     pub transfers: u8,
     pub walking_length: f64,
+    pub origin: u8,
 }
 
 impl ReachData {
-    pub fn with_time_and_dist(&self, time: Time, additional_walking_dist: f64) -> Self {
+    /// After walking until `time`, each second weighing `cost_weight`
+    pub fn with_time_and_dist(&self, time: Time, additional_walking_dist: f64, cost_weight: f64) -> Self {
         ReachData {
             timestamp: time,
+            cost: self.cost + (time - self.timestamp).0 * cost_weight,
             progress_trip_id: self.progress_trip_id,
             transfers: self.transfers,
             walking_length: self.walking_length + additional_walking_dist,
+            origin: self.origin,
         }
     }
 }
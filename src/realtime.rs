@@ -0,0 +1,203 @@
+use crate::configuration::Exclusions;
+use crate::gtfs_processing::{RouteStopSequence, StopsData, StopsWithTrips};
+use crate::network::Network;
+use crate::time::Time;
+use crate::BusPickupInfo;
+use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use gtfs_structure_2::gtfs_wrapper::{StopTime, Trip};
+use gtfs_structure_2::IdType;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeSet;
+
+/// A trip that only exists in the realtime feed
+pub struct AddedTrip {
+    /// Stop times hold the realtime arrivals and departures
+    pub trip: Trip,
+    pub start_date: NaiveDate,
+}
+
+/// Realtime state of a city's network, laid over its static [Network] without changing it.
+/// A new overlay replaces the whole previous one, so searches keep using the one they started with.
+#[derive(Default)]
+pub struct RealtimeOverlay {
+    /// Increases with every overlay built, so cached responses of older ones aren't reused
+    pub version: u64,
+    /// Realtime arrival and departure of each stop time, by trip and start date. None when the stop is skipped.
+    pub(crate) updates: FxHashMap<(IdType, NaiveDate), Vec<Option<(u32, u32)>>>,
+    pub(crate) canceled: FxHashSet<(IdType, NaiveDate)>,
+    pub(crate) added_trips: FxHashMap<IdType, AddedTrip>,
+    /// Pickups of added trips at each stop. Only stops served by the schedule can be found by the search.
+    pub(crate) added_pickups: StopsWithTrips,
+    /// Seconds that updated departures are later than scheduled, at most
+    pub(crate) max_delay: f64,
+    /// Seconds that updated departures are earlier than scheduled, at most
+    pub(crate) max_early: f64,
+    pub(crate) alerts: Vec<Alert>,
+}
+
+/// A realtime service alert, e.g. a line closed for the weekend
+pub struct Alert {
+    /// Text shown to riders
+    pub header: String,
+    /// POSIX times the alert is active between. Active at all times when empty.
+    pub active_periods: Vec<(Option<i64>, Option<i64>)>,
+    /// Whether there's no service at the affected stops, routes and trips, rather than only a notice
+    pub no_service: bool,
+    pub(crate) routes: FxHashSet<IdType>,
+    pub(crate) stops: FxHashSet<IdType>,
+    pub(crate) trips: FxHashSet<IdType>,
+    /// Stops affected for one route only, as (route, stop)
+    pub(crate) route_stops: FxHashSet<(IdType, IdType)>,
+}
+
+impl Alert {
+    pub(crate) fn is_active(&self, time: i64) -> bool {
+        self.active_periods.is_empty()
+            || self.active_periods.iter().any(|(start, end)| {
+                start.map_or(true, |start| start <= time) && end.map_or(true, |end| time < end)
+            })
+    }
+
+    /// Whether the alert is about a ride on the trip, between the stop times boarded and left at
+    pub(crate) fn affects_ride(&self, trip: &Trip, stop_times: &[StopTime]) -> bool {
+        self.routes.contains(&trip.route_id)
+            || self.trips.contains(&trip.id)
+            || stop_times.iter().any(|st| {
+                self.stops.contains(&st.stop_id) || self.route_stops.contains(&(trip.route_id, st.stop_id))
+            })
+    }
+}
+
+/// POSIX time of the start of a service day, which is noon minus 12h so that DST changes work out
+pub(crate) fn service_day_start(timezone: Tz, date: NaiveDate) -> Option<i64> {
+    let noon = timezone
+        .from_local_datetime(&date.and_hms_opt(12, 0, 0)?)
+        .single()?;
+    Some(noon.timestamp() - 12 * 3600)
+}
+
+impl RealtimeOverlay {
+    /// Alerts active at the POSIX time
+    pub(crate) fn active_alerts(&self, time: i64) -> impl Iterator<Item = &Alert> {
+        self.alerts.iter().filter(move |alert| alert.is_active(time))
+    }
+
+    /// Adds the stops, routes and trips without service at the POSIX time to the exclusions
+    pub(crate) fn add_closures(&self, exclusions: &mut Exclusions, time: i64) {
+        for alert in self.active_alerts(time).filter(|alert| alert.no_service) {
+            exclusions.routes.extend(&alert.routes);
+            exclusions.stops.extend(&alert.stops);
+            exclusions.trips.extend(&alert.trips);
+            exclusions.route_stops.extend(&alert.route_stops);
+        }
+    }
+}
+
+/// Static trip, or else a trip added by the realtime feed
+pub(crate) fn find_trip<'a>(
+    network: &Network<'a>,
+    realtime: Option<&'a RealtimeOverlay>,
+    trip_id: IdType,
+) -> Option<&'a Trip> {
+    network
+        .find_trip(trip_id)
+        .or_else(|| Some(&realtime?.added_trips.get(&trip_id)?.trip))
+}
+
+pub(crate) fn trip<'a>(network: &Network<'a>, realtime: Option<&'a RealtimeOverlay>, trip_id: IdType) -> &'a Trip {
+    find_trip(network, realtime, trip_id).unwrap()
+}
+
+/// Whether the trip runs on the service date, following the calendar unless the feed cancels it
+pub(crate) fn runs_on_date(
+    network: &Network,
+    realtime: Option<&RealtimeOverlay>,
+    trip: &Trip,
+    service_date: NaiveDate,
+) -> bool {
+    let Some(realtime) = realtime else {
+        return network.runs_on_date(trip.service_id, service_date);
+    };
+    if let Some(added) = realtime.added_trips.get(&trip.id) {
+        return added.start_date == service_date;
+    }
+    network.runs_on_date(trip.service_id, service_date)
+        && !realtime.canceled.contains(&(trip.id, service_date))
+}
+
+/// Times of one trip on one service day, from the realtime feed when it updates the trip
+pub(crate) struct TripTimes<'a> {
+    updates: Option<&'a [Option<(u32, u32)>]>,
+}
+
+impl<'a> TripTimes<'a> {
+    pub(crate) fn new(realtime: Option<&'a RealtimeOverlay>, trip_id: IdType, service_date: NaiveDate) -> Self {
+        TripTimes {
+            updates: realtime
+                .and_then(|realtime| realtime.updates.get(&(trip_id, service_date)))
+                .map(Vec::as_slice),
+        }
+    }
+
+    /// Arrival and departure at the stop time, on the timeline of its service day. None if the stop is skipped.
+    pub(crate) fn at(&self, st: &StopTime) -> Option<(u32, u32)> {
+        match self.updates {
+            Some(updates) => updates[st.index_of_stop_time],
+            None => Some((st.arrival_time.unwrap(), st.departure_time.unwrap())),
+        }
+    }
+
+    pub(crate) fn arrival(&self, st: &StopTime) -> Option<u32> {
+        self.at(st).map(|(arrival, _)| arrival)
+    }
+
+    pub(crate) fn departure(&self, st: &StopTime) -> Option<u32> {
+        self.at(st).map(|(_, departure)| departure)
+    }
+}
+
+/// Realtime departure of a pickup, already shifted onto the searched timeline. None if the stop is skipped.
+pub(crate) fn pickup_departure(
+    realtime: Option<&RealtimeOverlay>,
+    trip: &Trip,
+    pickup: &BusPickupInfo,
+    service_date: NaiveDate,
+) -> Option<Time> {
+    let times = TripTimes::new(realtime, trip.id, service_date);
+    if times.updates.is_none() {
+        return Some(pickup.timestamp);
+    }
+    let st = trip
+        .stop_times
+        .iter()
+        .find(|st| st.stop_sequence == pickup.stop_sequence_no)?;
+    let scheduled = st.departure_time.unwrap() as f64;
+    let departure = times.departure(st)? as f64;
+    Some(pickup.timestamp + (departure - scheduled))
+}
+
+/// Routes picking up at the stop, with their scheduled pickups and those of trips added by the realtime feed
+pub(crate) fn route_pickups<'a>(
+    stop: &'a StopsData,
+    realtime: Option<&'a RealtimeOverlay>,
+) -> impl Iterator<
+    Item = (
+        &'a RouteStopSequence,
+        &'a BTreeSet<BusPickupInfo>,
+        Option<&'a BTreeSet<BusPickupInfo>>,
+    ),
+> + 'a {
+    let added = realtime.and_then(|realtime| realtime.added_pickups.0.get(&stop.stop_id));
+    let scheduled = stop
+        .trips_with_time
+        .0
+        .iter()
+        .map(move |(route, pickups)| (route, pickups, added.and_then(|added| added.0.get(route))));
+    let added_only = added
+        .into_iter()
+        .flat_map(|added| added.0.iter())
+        .filter(|(route, _)| !stop.trips_with_time.0.contains_key(route))
+        .map(|(route, pickups)| (route, pickups, None));
+    scheduled.chain(added_only)
+}
@@ -1,4 +1,7 @@
-use crate::{TripsArena, STRAIGHT_WALKING_SPEED, WALKING_SPEED};
+use crate::bike_access::BikeAccess;
+use crate::configuration::WalkingParameters;
+use crate::trips_arena::{transfer_label, TRANSFER_LABELS};
+use crate::{TripsArena, NULL_ID};
 use gdal::vector::LayerAccess;
 use gdal::{Dataset, DatasetOptions, GdalOpenFlags};
 use geo_types::Point;
@@ -16,13 +19,14 @@ use std::sync::{Arc, Mutex};
 use crate::agencies::City;
 use crate::best_times::BestTimes;
 use crate::projection::get_proj_defn;
+use crate::realtime::RealtimeOverlay;
+use chrono::NaiveDate;
 use crate::time::Time;
 use serde::ser::SerializeTuple;
 
 use crate::reach_data::ReachData;
 
 pub type EdgeId = u64;
-const MAX_WALKING_HOURS: f64 = 0.40;
 
 #[derive(Clone)]
 struct EdgeData {
@@ -88,6 +92,20 @@ pub struct RoadStructure {
     pub rs: Arc<RoadStructureInner>,
     pub nb: BestTimes<NodeId>,
     pub trips_arena: TripsArena,
+    /// Walking parameters of the last search, also used to walk to the final destination
+    pub walking: WalkingParameters,
+    /// In Pareto mode, best times using at most 0, 1, 2 and any number of transfers
+    pub nb_by_transfers: Vec<BestTimes<NodeId>>,
+    /// Stops cycled to from each origin in the last search, to draw the bike leg
+    pub bike_access: Vec<Arc<BikeAccess>>,
+    /// Realtime overlay of the last search, so trip details show the same trips and times
+    pub realtime: Option<Arc<RealtimeOverlay>>,
+    /// Service date of the last search, which realtime updates are looked up by
+    pub service_date: NaiveDate,
+    /// When the last search started, to show the realtime alerts it was affected by
+    pub alerts_time: i64,
+    /// What-if scenario of the last search, whose network its trips are on
+    pub scenario: Option<String>,
 }
 
 impl RoadStructure {
@@ -96,27 +114,61 @@ impl RoadStructure {
     }
     pub fn clear_data(&mut self) {
         self.nb.clear();
+        self.nb_by_transfers.clear();
         self.trips_arena = TripsArena::default();
     }
 
-    pub fn is_first_reacher_to_stop(&self, stop_id: IdType, point: &[f64; 2], time: Time) -> bool {
-        let nodeid = &self.rs.nearest_node_to_point(point, Some(stop_id));
+    /// Keeps best times per transfer label, for `save_by_transfers`
+    pub fn set_pareto(&mut self, pareto: bool) {
+        self.trips_arena.set_pareto(pareto);
+        if !pareto {
+            self.nb_by_transfers.clear();
+        } else if self.nb_by_transfers.is_empty() {
+            self.nb_by_transfers = (0..TRANSFER_LABELS).map(|_| BestTimes::new()).collect();
+        }
+    }
 
-        self.nb
-            .get(nodeid)
-            .map(|a| a.timestamp)
-            .unwrap_or(Time::MAX)
-            > time
+    pub fn is_first_reacher_to_stop(
+        &self,
+        stop_id: IdType,
+        point: &[f64; 2],
+        cost: Time,
+        boardings: u8,
+    ) -> bool {
+        // Origins aren't stops, and can't share a cached node
+        let cache_key = (stop_id != NULL_ID).then_some(stop_id);
+        let nodeid = &self.rs.nearest_node_to_point(point, cache_key);
+        let nb = if self.nb_by_transfers.is_empty() {
+            &self.nb
+        } else {
+            &self.nb_by_transfers[transfer_label(boardings)]
+        };
+
+        nb.get(nodeid).map(|a| a.cost).unwrap_or(Time::MAX) > cost
     }
 
     pub fn add_observation(&mut self, point: &[f64; 2], data: ReachData) {
-        self.rs.explore_from_point(point, data, &mut self.nb);
+        // Reaching a node with k transfers also reaches it with at most k + 1, k + 2... transfers
+        let label = transfer_label(data.transfers);
+        for nb in self.nb_by_transfers.iter_mut().skip(label) {
+            self.rs
+                .explore_from_point(point, data.clone(), nb, &self.walking);
+        }
+        self.rs
+            .explore_from_point(point, data, &mut self.nb, &self.walking);
     }
     pub fn new_city(city: City) -> Self {
         Self {
             rs: Arc::new(RoadStructureInner::new(city)),
             nb: BestTimes::new(),
             trips_arena: TripsArena::default(),
+            walking: WalkingParameters::default(),
+            nb_by_transfers: Vec::new(),
+            bike_access: Vec::new(),
+            realtime: None,
+            alerts_time: 0,
+            scenario: None,
+            service_date: NaiveDate::default(),
         }
     }
 
@@ -125,6 +177,13 @@ impl RoadStructure {
             rs,
             nb: BestTimes::new(),
             trips_arena: TripsArena::default(),
+            walking: WalkingParameters::default(),
+            nb_by_transfers: Vec::new(),
+            bike_access: Vec::new(),
+            realtime: None,
+            alerts_time: 0,
+            scenario: None,
+            service_date: NaiveDate::default(),
         }
     }
 
@@ -142,7 +201,26 @@ impl RoadStructure {
     }
 
     pub fn save(&self) -> Vec<EdgeTime> {
-        self.rs.calculate_best_times(&self.nb)
+        self.save_best_times(&self.nb)
+    }
+
+    /// Edge times using at most 0, 1, 2 and any number of transfers. Empty unless in Pareto mode.
+    pub fn save_by_transfers(&self) -> Vec<Vec<EdgeTime>> {
+        self.nb_by_transfers
+            .iter()
+            .map(|nb| self.save_best_times(nb))
+            .collect()
+    }
+
+    fn save_best_times(&self, nb: &BestTimes<NodeId>) -> Vec<EdgeTime> {
+        let search_mode = self.trips_arena.search_mode();
+        let mut edge_times = self.rs.calculate_best_times(nb);
+        // Best times are stored on the search timeline. Report clock times, which for arrive-by
+        // searches is the latest departure time.
+        for edge_time in &mut edge_times {
+            edge_time.time = search_mode.to_clock_time(Time(edge_time.time)).0;
+        }
+        edge_times
     }
 }
 
@@ -150,6 +228,10 @@ impl RoadStructure {
 pub struct EdgeTime {
     pub edge_id: EdgeId,
     pub time: f64,
+    /// Generalized cost on the search timeline
+    pub cost: f64,
+    /// Origin reaching the edge first
+    pub origin: u8,
 }
 
 #[derive(Debug)]
@@ -182,10 +264,11 @@ impl RoadStructureInner {
         to_explore: &mut VecDeque<(NodeId, ReachData)>,
         node_best_times: &mut BestTimes<NodeId>,
         do_edge_based_search: bool,
+        walking: &WalkingParameters,
     ) {
         if node_best_times
             .get(&node)
-            .map(|a| a.timestamp < base_time.timestamp)
+            .map(|a| a.cost < base_time.cost)
             .unwrap_or(false)
         {
             return;
@@ -197,8 +280,9 @@ impl RoadStructureInner {
 
                 let other_node = edge.get_other_node(node);
                 let time_to_other_node = base_time.with_time_and_dist(
-                    base_time.timestamp + edge.length / WALKING_SPEED,
+                    base_time.timestamp + edge.length / walking.speed,
                     edge.length,
+                    walking.cost_weight,
                 );
                 if node_best_times.set_best_time(other_node, time_to_other_node.clone()) {
                     // This node has it's best time beat.
@@ -245,46 +329,60 @@ impl RoadStructureInner {
         point: &[f64; 2],
         base_time: ReachData,
         node_best_times: &mut BestTimes<NodeId>,
+        walking: &WalkingParameters,
     ) {
         const EDGE_BASED_SEARCH: bool = true;
-        const WALKING_DISTANCE: f64 = if EDGE_BASED_SEARCH { 100.0 } else { 1100.0 };
+        let walking_distance = if EDGE_BASED_SEARCH {
+            walking.snap_distance_m
+        } else {
+            1100.0
+        };
         // Explore all reachable roads from a particular point
         let mut queue = VecDeque::new();
 
         for closest_node in
-            self.distance_nearest_nodes_to_point(*point, WALKING_DISTANCE * WALKING_DISTANCE)
+            self.distance_nearest_nodes_to_point(*point, walking_distance * walking_distance)
         {
             let distance_to_closest_node = closest_node.distance_2(point).sqrt();
-            let time_to_closest_node = distance_to_closest_node / STRAIGHT_WALKING_SPEED;
+            let time_to_closest_node = distance_to_closest_node / walking.straight_speed;
 
             self.explore_from_node(
                 closest_node.data,
                 &base_time.with_time_and_dist(
                     base_time.timestamp + time_to_closest_node,
                     distance_to_closest_node,
+                    walking.cost_weight,
                 ),
                 &mut queue,
                 node_best_times,
                 // Don't do edge based search, only distance search
                 EDGE_BASED_SEARCH,
+                walking,
             );
         }
 
         if EDGE_BASED_SEARCH {
             // Unused because we don't want to explore based on road positions anymore
             while let Some((item, rd)) = queue.pop_back() {
-                let set_time = rd.timestamp;
-                let time = node_best_times.get(&item).unwrap().timestamp;
-                if time != set_time {
-                    debug_assert!(time < set_time);
+                let set_cost = rd.cost;
+                let cost = node_best_times.get(&item).unwrap().cost;
+                if cost != set_cost {
+                    debug_assert!(cost < set_cost);
                     continue;
                 }
 
-                if time - base_time.timestamp >= Time(3600.0 * MAX_WALKING_HOURS) {
+                if rd.timestamp - base_time.timestamp >= Time(walking.max_walking_secs) {
                     continue;
                 }
 
-                self.explore_from_node(item, &rd, &mut queue, node_best_times, EDGE_BASED_SEARCH);
+                self.explore_from_node(
+                    item,
+                    &rd,
+                    &mut queue,
+                    node_best_times,
+                    EDGE_BASED_SEARCH,
+                    walking,
+                );
             }
         }
     }
@@ -377,13 +475,19 @@ impl RoadStructureInner {
             let from_time = b.get(&from_node);
             let to_time = b.get(&to_node);
 
-            if from_time.and(to_time).is_some() {
-                let average_time =
-                    (from_time.unwrap().timestamp + to_time.unwrap().timestamp) / 2.0;
+            if let (Some(from_time), Some(to_time)) = (from_time, to_time) {
+                let average_time = (from_time.timestamp + to_time.timestamp) / 2.0;
                 max_time = max_time.max(average_time);
+                let best = if from_time.cost <= to_time.cost {
+                    from_time
+                } else {
+                    to_time
+                };
                 edge_times.push(EdgeTime {
                     edge_id: *edge_id,
                     time: average_time.0,
+                    cost: ((from_time.cost + to_time.cost) / 2.0).0,
+                    origin: best.origin,
                 });
             }
         }
@@ -0,0 +1,753 @@
+use crate::agencies::City;
+use crate::gtfs_processing::{RoutePickupTimes, SpatialStopsWithTrips, StopsData, StopsWithTrips};
+use crate::gtfs_setup::get_agency_id_from_short_name;
+use crate::network::SpatialStop;
+use crate::{projection, BusPickupInfo};
+use anyhow::{anyhow, bail, Context};
+use gtfs_structure_2::calendar::{Calendar, Service};
+use gtfs_structure_2::fares::Fares;
+use gtfs_structure_2::gtfs_wrapper::{
+    AgencyId, Availability1, DirectionType, Gtfs1, Route, RouteType, Stop, StopTime, Trip,
+};
+use gtfs_structure_2::shape::Shape;
+use gtfs_structure_2::IdType;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// What-if scenarios, one JSON file each. The file name without `.json` is the scenario ID.
+const SCENARIOS_PATH: &str = "city-gtfs/scenarios";
+/// Stops, routes and trips of scenarios get IDs from here on, away from the IDs of the feeds
+/// and of trips added by realtime feeds
+const SCENARIO_IDS: u64 = 1 << 62;
+
+/// A scenario file, e.g. a proposed line, with route 29 running half as often and route 35 cut after 22:00:
+/// ```json
+/// {
+///   "city": "Toronto",
+///   "lines": [{
+///     "agency": "TTC", "name": "3", "longName": "Ontario Line", "mode": "subway", "color": "F7931D",
+///     "headwaySecs": 180, "startTime": 21600, "endTime": 90000,
+///     "stops": [
+///       {"name": "Exhibition", "latitude": 43.6366, "longitude": -79.4186},
+///       {"stopId": "14414", "runTimeSecs": 150, "dwellSecs": 30}
+///     ]
+///   }],
+///   "operations": [
+///     {"type": "scaleFrequency", "agency": "TTC", "routes": ["29"], "factor": 0.5},
+///     {"type": "removeTrips", "agency": "TTC", "routes": ["35"], "startTime": 79200}
+///   ]
+/// }
+/// ```
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioFile {
+    pub city: City,
+    #[serde(default)]
+    pub lines: Vec<LineSpec>,
+    /// Applied in order, after adding the lines
+    #[serde(default)]
+    pub operations: Vec<OperationSpec>,
+}
+
+/// A change to the service of existing routes, for the trips leaving their first stop in a time band
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OperationSpec {
+    /// Runs `factor` times as many trips, e.g. 0.5 for every 20 minutes instead of every 10.
+    /// Trips are spread evenly between the first and last trip in the band, each following the run
+    /// times of the trip before it.
+    ScaleFrequency {
+        #[serde(flatten)]
+        routes: RoutesSpec,
+        factor: f64,
+    },
+    RemoveTrips {
+        #[serde(flatten)]
+        routes: RoutesSpec,
+    },
+    /// Shortens the time from the first stop to each stop by `percent`. Negative to slow down.
+    SpeedUp {
+        #[serde(flatten)]
+        routes: RoutesSpec,
+        percent: f64,
+    },
+}
+
+/// Routes of an agency, by their ID in its feed or their short name, and a time band
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutesSpec {
+    pub agency: String,
+    pub routes: Vec<String>,
+    /// Seconds after the start of the service day. The band is open on sides without a time.
+    pub start_time: Option<u32>,
+    pub end_time: Option<u32>,
+}
+
+impl RoutesSpec {
+    fn contains_time(&self, time: u32) -> bool {
+        self.start_time.map_or(true, |start| start <= time)
+            && self.end_time.map_or(true, |end| time < end)
+    }
+}
+
+/// A hypothetical line, running every `headway_secs` between `start_time` and `end_time` every day
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineSpec {
+    /// Short name of the agency running the line, so it's selected along with the agency
+    pub agency: String,
+    pub name: String,
+    #[serde(default)]
+    pub long_name: String,
+    /// bus, tram, subway, rail...
+    pub mode: String,
+    #[serde(default)]
+    pub color: String,
+    pub headway_secs: u32,
+    /// Seconds after the start of the service day that the first and the last trips leave
+    pub start_time: u32,
+    pub end_time: u32,
+    /// Whether trips also run from the last stop back to the first
+    #[serde(default = "default_both_directions")]
+    pub both_directions: bool,
+    /// In the order they are served
+    pub stops: Vec<LineStopSpec>,
+}
+
+fn default_both_directions() -> bool {
+    true
+}
+
+/// A stop of a line: either an existing stop of the agency's feed, to transfer there, or a new one
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineStopSpec {
+    pub stop_id: Option<String>,
+    pub name: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Seconds from the previous stop
+    #[serde(default)]
+    pub run_time_secs: u32,
+    #[serde(default)]
+    pub dwell_secs: u32,
+}
+
+/// Changes of a scenario to the city's network, before they are indexed
+#[derive(Default)]
+pub struct ScenarioChanges {
+    stops: Vec<Stop>,
+    routes: Vec<Route>,
+    /// Trips added, some replacing removed trips of the city
+    trips: FxHashMap<IdType, Trip>,
+    removed_trips: FxHashSet<IdType>,
+    shapes: Vec<(IdType, Vec<Shape>)>,
+    /// Services of the added trips that the city doesn't have
+    calendar: Calendar,
+    versions: FxHashMap<AgencyId, FeedVersion>,
+    /// IDs given out so far
+    ids: u64,
+}
+
+/// Another version of an agency's feed, replacing the current one
+pub(crate) struct FeedVersion {
+    /// Numeric IDs given to the non-numeric IDs of the version, see [Gtfs1::lookup_id]. Kept apart
+    /// from the city's, which may have given the same IDs other numbers.
+    pub(crate) gtfs_ids: FxHashMap<String, u64>,
+    pub(crate) fares: Option<Fares>,
+}
+
+/// A what-if scenario of a city, or another version of some of its feeds, laid over the city's
+/// network without changing it. Searches see it through a [Network].
+pub struct Scenario {
+    /// Stops, routes and trips added, or replacing the city's with the same ID
+    pub(crate) stops: FxHashMap<IdType, Stop>,
+    pub(crate) routes: FxHashMap<IdType, Route>,
+    pub(crate) trips: FxHashMap<IdType, Trip>,
+    pub(crate) shapes: FxHashMap<IdType, Vec<Shape>>,
+    pub(crate) calendar: Calendar,
+    /// Agencies whose feed is replaced by another version
+    pub(crate) versions: FxHashMap<AgencyId, FeedVersion>,
+    /// Stops of the city whose pickups changed, with the pickups left and added
+    pub(crate) changed_stops: FxHashMap<IdType, SpatialStop>,
+    /// Stops of the scenario with pickups, by location
+    pub(crate) added_stops: RTree<SpatialStop>,
+}
+
+impl Scenario {
+    // Indexes the pickups of the stops whose trips change: the city's pickups there less those of
+    // removed trips, and the pickups of added trips
+    fn new(city: &City, base: &Gtfs1, spatial: &SpatialStopsWithTrips, changes: ScenarioChanges) -> Self {
+        let stops: FxHashMap<IdType, Stop> = changes.stops.into_iter().map(|stop| (stop.id, stop)).collect();
+        let changed_stop_ids: FxHashSet<IdType> = changes
+            .removed_trips
+            .iter()
+            .filter_map(|trip_id| base.trips.get(trip_id))
+            .chain(changes.trips.values())
+            .flat_map(|trip| trip.stop_times.iter().map(|st| st.stop_id))
+            .collect();
+
+        let mut pickups = StopsWithTrips::default();
+        for stop_id in changed_stop_ids {
+            let kept = spatial.get_stop(city, base, stop_id).map(|stop| {
+                stop.data
+                    .trips_with_time
+                    .0
+                    .iter()
+                    .map(|(route_info, route_pickups)| {
+                        let route_pickups: BTreeSet<BusPickupInfo> = route_pickups
+                            .iter()
+                            .filter(|pickup| !changes.removed_trips.contains(&pickup.trip_id))
+                            .cloned()
+                            .collect();
+                        (route_info.clone(), route_pickups)
+                    })
+                    .filter(|(_, route_pickups)| !route_pickups.is_empty())
+                    .collect()
+            });
+            pickups.0.insert(stop_id, RoutePickupTimes(kept.unwrap_or_default()));
+        }
+        for trip in changes.trips.values() {
+            for st in &trip.stop_times {
+                pickups.add_stop(st, trip);
+            }
+        }
+
+        let mut changed_stops = FxHashMap::default();
+        let mut added_stops = Vec::new();
+        for (stop_id, trips_with_time) in pickups.0 {
+            let data = StopsData {
+                trips_with_time,
+                stop_id,
+            };
+            match stops.get(&stop_id) {
+                Some(stop) => added_stops.push(GeomWithData::new(projection::project_stop(city, stop), data)),
+                None => {
+                    let point = projection::project_stop(city, &base.stops[&stop_id]);
+                    changed_stops.insert(stop_id, GeomWithData::new(point, data));
+                }
+            }
+        }
+
+        Scenario {
+            stops,
+            routes: changes.routes.into_iter().map(|route| (route.id, route)).collect(),
+            trips: changes.trips,
+            shapes: changes.shapes.into_iter().collect(),
+            calendar: changes.calendar,
+            versions: changes.versions,
+            changed_stops,
+            added_stops: RTree::bulk_load(added_stops),
+        }
+    }
+
+    /// Other versions of some agencies' feeds, each replacing all trips of the agency's current
+    /// feed. A version keeps the agency ID it had when its cache was made, which can be another
+    /// agency's by now, so it's given the ID of the agency it replaces.
+    pub fn feed_version(
+        city: &City,
+        base: &Gtfs1,
+        spatial: &SpatialStopsWithTrips,
+        feeds: Vec<Gtfs1>,
+    ) -> anyhow::Result<Self> {
+        let mut changes = ScenarioChanges::default();
+        for mut feed in feeds {
+            let agency_id = get_agency_id_from_short_name(&feed.agency_name)
+                .ok_or_else(|| anyhow!("No current feed of agency {}", feed.agency_name))?;
+            feed.set_agency_id(agency_id);
+            changes
+                .removed_trips
+                .extend(base.trips.keys().filter(|trip_id| trip_id.0 == agency_id));
+            changes.stops.extend(feed.stops.into_values());
+            changes.routes.extend(feed.routes.into_values());
+            changes.trips.extend(feed.trips);
+            changes.shapes.extend(feed.shapes.into_iter().chain(feed.generated_shapes));
+            changes.calendar.extend(feed.calendar);
+            changes.versions.insert(
+                agency_id,
+                FeedVersion {
+                    gtfs_ids: feed.gtfs_ids,
+                    fares: feed.fares.remove(&agency_id),
+                },
+            );
+        }
+        Ok(Scenario::new(city, base, spatial, changes))
+    }
+}
+
+impl ScenarioChanges {
+    fn next_id(&mut self) -> u64 {
+        self.ids += 1;
+        SCENARIO_IDS + self.ids
+    }
+
+    fn add_line(&mut self, base: &Gtfs1, line: &LineSpec) -> anyhow::Result<()> {
+        let agency_id = get_agency_id_from_short_name(&line.agency)
+            .ok_or_else(|| anyhow!("Unknown agency {}", line.agency))?;
+        let route_type =
+            RouteType::try_from(line.mode.as_str()).map_err(|_| anyhow!("Unknown mode {}", line.mode))?;
+        if line.stops.len() < 2 {
+            bail!("Line {} needs at least two stops", line.name);
+        }
+        if line.headway_secs == 0 || line.start_time > line.end_time {
+            bail!("Line {} needs a headway and a start time before its end time", line.name);
+        }
+
+        // Each stop with the seconds from the previous stop, and its dwell time
+        let mut forward: Vec<(IdType, u32, u32)> = Vec::with_capacity(line.stops.len());
+        for stop in &line.stops {
+            let stop_id = match (&stop.stop_id, stop.latitude, stop.longitude) {
+                (Some(stop_id), _, _) => base
+                    .lookup_id(agency_id, stop_id)
+                    .filter(|stop_id| base.stops.contains_key(stop_id))
+                    .ok_or_else(|| anyhow!("Unknown stop {stop_id} of {}", line.agency))?,
+                (None, Some(latitude), Some(longitude)) => {
+                    let id = (agency_id, self.next_id());
+                    self.stops.push(Stop {
+                        id,
+                        name: stop.name.clone().unwrap_or_default(),
+                        latitude: Some(latitude),
+                        longitude: Some(longitude),
+                        wheelchair_boarding: Availability1::Available,
+                        ..Default::default()
+                    });
+                    id
+                }
+                _ => bail!("Stops of line {} need a stop ID or coordinates", line.name),
+            };
+            forward.push((stop_id, stop.run_time_secs, stop.dwell_secs));
+        }
+
+        let route_id = (agency_id, self.next_id());
+        self.routes.push(Route {
+            id: route_id,
+            short_name: line.name.clone(),
+            long_name: line.long_name.clone(),
+            route_type,
+            color: line.color.clone(),
+            text_color: "FFFFFF".to_string(),
+            ..Default::default()
+        });
+
+        let service_id = (agency_id, self.next_id());
+        self.calendar
+            .services
+            .insert(service_id, Service::every_day(service_id));
+
+        let mut directions = vec![(DirectionType::Outbound, forward.clone())];
+        if line.both_directions {
+            // Going back, a stop is reached with the run time of the stop after it
+            let backward = (0..forward.len())
+                .rev()
+                .map(|i| {
+                    let run_time = forward.get(i + 1).map_or(0, |(_, run_time, _)| *run_time);
+                    (forward[i].0, run_time, forward[i].2)
+                })
+                .collect();
+            directions.push((DirectionType::Inbound, backward));
+        }
+
+        for (direction, line_stops) in directions {
+            let shape: Vec<Shape> = line_stops
+                .iter()
+                .enumerate()
+                .map(|(index, (stop_id, _, _))| {
+                    let stop = base
+                        .stops
+                        .get(stop_id)
+                        .or_else(|| self.stops.iter().find(|stop| stop.id == *stop_id))
+                        .unwrap();
+                    Shape {
+                        id: (0, 0),
+                        latitude: stop.latitude.unwrap(),
+                        longitude: stop.longitude.unwrap(),
+                        sequence: index,
+                        dist_traveled: None,
+                    }
+                })
+                .collect();
+            let shape_id = (agency_id, self.next_id());
+            self.shapes.push((shape_id, shape));
+
+            for departure in (line.start_time..=line.end_time).step_by(line.headway_secs as usize) {
+                let trip_id = (agency_id, self.next_id());
+                let mut time = departure;
+                let mut stop_times = Vec::with_capacity(line_stops.len());
+                for (index, (stop_id, run_time, dwell)) in line_stops.iter().enumerate() {
+                    if index > 0 {
+                        time += run_time;
+                    }
+                    stop_times.push(StopTime {
+                        arrival_time: Some(time),
+                        departure_time: Some(time + dwell),
+                        stop_sequence: index as u16,
+                        stop_id: *stop_id,
+                        trip_id,
+                        index_of_stop_time: index,
+                        // Shape points are the stops
+                        shape_index: index as f32,
+                        shape_dist_traveled: None,
+                    });
+                    time += dwell;
+                }
+                self.trips.insert(trip_id, Trip {
+                    id: trip_id,
+                    service_id,
+                    route_id,
+                    stop_times,
+                    shape_id: Some(shape_id),
+                    trip_headsign: None,
+                    trip_short_name: None,
+                    direction_id: Some(direction),
+                    block_id: None,
+                    wheelchair_accessible: Availability1::Available,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_routes(&self, base: &Gtfs1, spec: &RoutesSpec) -> anyhow::Result<FxHashSet<IdType>> {
+        let agency_id = get_agency_id_from_short_name(&spec.agency)
+            .ok_or_else(|| anyhow!("Unknown agency {}", spec.agency))?;
+        let mut route_ids = FxHashSet::default();
+        for route in &spec.routes {
+            let by_id = base
+                .lookup_id(agency_id, route)
+                .filter(|route_id| base.routes.contains_key(route_id));
+            // A short name can be shared by the routes of a line's branches
+            let by_short_name = base
+                .routes
+                .values()
+                .chain(&self.routes)
+                .filter(|r| r.id.0 == agency_id && r.short_name == *route)
+                .map(|r| r.id);
+            let found: Vec<IdType> = match by_id {
+                Some(route_id) => vec![route_id],
+                None => by_short_name.collect(),
+            };
+            if found.is_empty() {
+                bail!("Unknown route {route} of {}", spec.agency);
+            }
+            route_ids.extend(found);
+        }
+        Ok(route_ids)
+    }
+
+    /// Trips of the routes leaving their first stop within the band, as changed so far
+    fn trips_in_band(&self, base: &Gtfs1, spec: &RoutesSpec, routes: &FxHashSet<IdType>) -> Vec<Trip> {
+        base.trips
+            .values()
+            .filter(|trip| !self.removed_trips.contains(&trip.id))
+            .chain(self.trips.values())
+            .filter(|trip| routes.contains(&trip.route_id) && spec.contains_time(first_departure(trip)))
+            .cloned()
+            .collect()
+    }
+
+    fn remove_trip(&mut self, base: &Gtfs1, trip_id: IdType) {
+        self.trips.remove(&trip_id);
+        if base.trips.contains_key(&trip_id) {
+            self.removed_trips.insert(trip_id);
+        }
+    }
+
+    fn apply_operation(&mut self, base: &Gtfs1, operation: &OperationSpec) -> anyhow::Result<()> {
+        match operation {
+            OperationSpec::ScaleFrequency { routes, factor } => {
+                if !(*factor > 0.0 && *factor <= 10.0) {
+                    bail!("Invalid frequency factor {factor}");
+                }
+                let route_ids = self.resolve_routes(base, routes)?;
+                self.scale_frequency(base, self.trips_in_band(base, routes, &route_ids), *factor);
+            }
+            OperationSpec::RemoveTrips { routes } => {
+                let route_ids = self.resolve_routes(base, routes)?;
+                for trip in self.trips_in_band(base, routes, &route_ids) {
+                    self.remove_trip(base, trip.id);
+                }
+            }
+            OperationSpec::SpeedUp { routes, percent } => {
+                if !(-100.0 < *percent && *percent < 100.0) {
+                    bail!("Invalid speedup {percent}%");
+                }
+                let route_ids = self.resolve_routes(base, routes)?;
+                let scale = 1.0 - percent / 100.0;
+                for mut trip in self.trips_in_band(base, routes, &route_ids) {
+                    let start = first_departure(&trip) as f64;
+                    let scaled = |time: Option<u32>| {
+                        time.map(|time| (start + (time as f64 - start) * scale).round() as u32)
+                    };
+                    // Replaced by a trip of the scenario, as the city's network keeps the original
+                    self.remove_trip(base, trip.id);
+                    trip.id = (trip.id.0, self.next_id());
+                    for st in &mut trip.stop_times {
+                        st.trip_id = trip.id;
+                        st.arrival_time = scaled(st.arrival_time);
+                        st.departure_time = scaled(st.departure_time);
+                    }
+                    self.trips.insert(trip.id, trip);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Replaces the trips of each route, direction and service by `factor` times as many trips,
+    // between the same first and last departures so none leave outside the band
+    fn scale_frequency(&mut self, base: &Gtfs1, trips: Vec<Trip>, factor: f64) {
+        let mut groups: FxHashMap<(IdType, bool, IdType), Vec<Trip>> = FxHashMap::default();
+        for trip in trips {
+            let inbound = trip.direction_id == Some(DirectionType::Inbound);
+            groups
+                .entry((trip.route_id, inbound, trip.service_id))
+                .or_default()
+                .push(trip);
+        }
+
+        for mut group in groups.into_values() {
+            // A single trip has no headway to scale
+            if group.len() < 2 {
+                continue;
+            }
+            group.sort_by_key(first_departure);
+            let first = first_departure(&group[0]) as f64;
+            let last = first_departure(group.last().unwrap()) as f64;
+            let count = ((group.len() as f64 * factor).round() as usize).max(1);
+            let headway = if count > 1 {
+                (last - first) / (count - 1) as f64
+            } else {
+                0.0
+            };
+
+            let mut scaled = Vec::with_capacity(count);
+            for k in 0..count {
+                let departure = (first + k as f64 * headway).round() as u32;
+                let template = group
+                    .iter()
+                    .rev()
+                    .find(|trip| first_departure(trip) <= departure)
+                    .unwrap();
+                let shift = departure - first_departure(template);
+                let id = (template.id.0, self.next_id());
+                let mut trip = template.clone();
+                trip.id = id;
+                // The trips before and after it in the block aren't timed for it anymore
+                trip.block_id = None;
+                for st in &mut trip.stop_times {
+                    st.trip_id = id;
+                    st.arrival_time = st.arrival_time.map(|time| time + shift);
+                    st.departure_time = st.departure_time.map(|time| time + shift);
+                }
+                scaled.push(trip);
+            }
+
+            for trip in &group {
+                self.remove_trip(base, trip.id);
+            }
+            self.trips.extend(scaled.into_iter().map(|trip| (trip.id, trip)));
+        }
+    }
+
+    fn new(base: &Gtfs1, file: &ScenarioFile) -> anyhow::Result<Self> {
+        let mut changes = ScenarioChanges::default();
+        for line in &file.lines {
+            changes
+                .add_line(base, line)
+                .with_context(|| format!("Invalid line {}", line.name))?;
+        }
+        for (index, operation) in file.operations.iter().enumerate() {
+            changes
+                .apply_operation(base, operation)
+                .with_context(|| format!("Invalid operation {index}"))?;
+        }
+        Ok(changes)
+    }
+}
+
+fn first_departure(trip: &Trip) -> u32 {
+    trip.stop_times[0].departure_time.unwrap()
+}
+
+fn read_scenario(path: &Path) -> anyhow::Result<ScenarioFile> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+/// Scenarios of the city by ID, from the scenario files. Invalid files are skipped.
+pub fn load_scenarios(city: City, base: &Gtfs1, spatial: &SpatialStopsWithTrips) -> FxHashMap<String, Scenario> {
+    let Ok(entries) = std::fs::read_dir(SCENARIOS_PATH) else {
+        return FxHashMap::default();
+    };
+    let mut scenarios = FxHashMap::default();
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let changes = read_scenario(&path).and_then(|file| {
+            if file.city != city {
+                return Ok(None);
+            }
+            ScenarioChanges::new(base, &file).map(Some)
+        });
+        match changes {
+            Ok(Some(changes)) => {
+                log::info!("Loaded scenario {id} for {city:?}");
+                scenarios.insert(id.to_string(), Scenario::new(&city, base, spatial, changes));
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Skipping scenario {path:?}: {e:#}"),
+        }
+    }
+    scenarios
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gtfs_setup;
+    use crate::network::Network;
+    use gtfs_structure_2::test_gtfs::{test_trip, TestGtfs};
+
+    const CITY: City = City::Toronto;
+
+    fn test_stop(latitude: f64) -> Stop {
+        Stop {
+            latitude: Some(latitude),
+            longitude: Some(-79.4),
+            ..Default::default()
+        }
+    }
+
+    // Agency `agency_id` with trip 1 from stop 1 to stop 2
+    fn agency_gtfs(agency_id: u16, departure: u32) -> Gtfs1 {
+        let trip = Trip {
+            direction_id: Some(DirectionType::Outbound),
+            ..test_trip(1, 1, &[(1, Some(departure)), (2, Some(departure + 300))])
+        };
+        let mut gtfs = TestGtfs::default()
+            .stop(1, test_stop(43.65))
+            .stop(2, test_stop(43.66))
+            .trip(trip)
+            .build();
+        gtfs.set_agency_id(agency_id);
+        gtfs
+    }
+
+    fn spatial(gtfs: &Gtfs1) -> SpatialStopsWithTrips {
+        gtfs_setup::generate_stops_trips(gtfs).into_spatial(&CITY, gtfs)
+    }
+
+    // Trips picking up at the stop, with their departure
+    fn departures(network: Network, stop_id: IdType) -> Vec<(IdType, u32)> {
+        let Some(stop) = network.get_stop(&CITY, stop_id) else {
+            return Vec::new();
+        };
+        stop.data
+            .trips_with_time
+            .0
+            .values()
+            .flatten()
+            .map(|pickup| (pickup.trip_id, pickup.timestamp.0 as u32))
+            .collect()
+    }
+
+    #[test]
+    fn test_feed_version_with_another_agencys_id() {
+        gtfs_setup::register_agency("Versioned agency", 0);
+        let gtfs = agency_gtfs(0, 0).merge(agency_gtfs(1, 100));
+        let spatial = spatial(&gtfs);
+        // Cached when agency 1 was the one it replaces
+        let mut version = agency_gtfs(1, 200);
+        version.agency_name = "Versioned agency".to_string();
+
+        let scenario = Scenario::feed_version(&CITY, &gtfs, &spatial, vec![version]).unwrap();
+        let network = Network {
+            scenario: Some(&scenario),
+            ..Network::new(&gtfs, &spatial)
+        };
+
+        // The version replaces the trips of agency 0 under its ID, and agency 1 keeps its own
+        assert_eq!(departures(network, (0, 1)), vec![((0, 1), 200)]);
+        assert_eq!(departures(network, (1, 1)), vec![((1, 1), 100)]);
+        assert_eq!(network.trip((0, 1)).stop_times[0].departure_time, Some(200));
+        // The city's network is unchanged
+        assert_eq!(departures(Network::new(&gtfs, &spatial), (0, 1)), vec![((0, 1), 0)]);
+        assert_eq!(gtfs.trips[&(0, 1)].stop_times[0].departure_time, Some(0));
+    }
+
+    #[test]
+    fn test_scenario_leaves_city_network_unchanged() {
+        let gtfs = agency_gtfs(0, 0);
+        let spatial = spatial(&gtfs);
+        // The city's trip, leaving 10 minutes later
+        let mut changes = ScenarioChanges::default();
+        let mut trip = gtfs.trips[&(0, 1)].clone();
+        changes.remove_trip(&gtfs, trip.id);
+        trip.id = (0, changes.next_id());
+        for st in &mut trip.stop_times {
+            st.trip_id = trip.id;
+            st.departure_time = st.departure_time.map(|time| time + 600);
+        }
+        let scenario_trip = trip.id;
+        changes.trips.insert(trip.id, trip);
+
+        let scenario = Scenario::new(&CITY, &gtfs, &spatial, changes);
+        let city_network = Network::new(&gtfs, &spatial);
+        let network = Network {
+            scenario: Some(&scenario),
+            ..city_network
+        };
+
+        assert_eq!(departures(network, (0, 1)), vec![(scenario_trip, 600)]);
+        assert_eq!(departures(city_network, (0, 1)), vec![((0, 1), 0)]);
+        assert!(city_network.find_trip(scenario_trip).is_none());
+    }
+
+    #[test]
+    fn test_line_runs_every_day() {
+        gtfs_setup::register_agency("Scenario line agency", 0);
+        let line: LineSpec = serde_json::from_str(
+            r#"{
+                "agency": "Scenario line agency", "name": "1", "mode": "bus",
+                "headwaySecs": 600, "startTime": 0, "endTime": 600,
+                "stops": [
+                    {"latitude": 43.65, "longitude": -79.4},
+                    {"latitude": 43.66, "longitude": -79.4, "runTimeSecs": 120}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let gtfs = TestGtfs::default().build();
+        let spatial = spatial(&gtfs);
+        let mut changes = ScenarioChanges::default();
+        changes.add_line(&gtfs, &line).unwrap();
+        let scenario = Scenario::new(&CITY, &gtfs, &spatial, changes);
+        let network = Network {
+            scenario: Some(&scenario),
+            ..Network::new(&gtfs, &spatial)
+        };
+
+        // Both directions, leaving at 0 and 600
+        assert_eq!(scenario.trips.len(), 4);
+        for trip in scenario.trips.values() {
+            // A week from Monday
+            for day in 1..=7 {
+                let date = chrono::NaiveDate::from_ymd_opt(2026, 6, day).unwrap();
+                assert!(network.runs_on_date(trip.service_id, date));
+            }
+        }
+        // The new stops are found by location, with the pickups of both directions
+        let point = projection::project_lng_lat(&CITY, -79.4, 43.65);
+        let (nearest, _) = network.stops_near(&point, 1.0)[0];
+        assert_eq!(nearest.data.trips_with_time.0.values().flatten().count(), 4);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::agencies::City;
+use crate::configuration::{Configuration, SearchMode, WalkingParameters};
+use crate::projection;
+use crate::time::Time;
+use gtfs_structure_2::gtfs_wrapper::{Gtfs1, Pathway, PathwayMode};
+use gtfs_structure_2::IdType;
+use rstar::PointDistance;
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Climbing or descending one level by stairs or escalator, when the pathway has no traversal time
+const SECS_PER_LEVEL: f64 = 15.0;
+/// Waiting for and riding an elevator, when the pathway has no traversal time
+const ELEVATOR_SECS: f64 = 60.0;
+
+/// Which way pathways are followed from a location
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Direction {
+    /// Walking away from the location
+    Outward,
+    /// Walking towards the location
+    Inward,
+}
+
+impl Direction {
+    /// Leaving the frontier stop when departing at a time, or walking to it when arriving by a time
+    pub(crate) fn from_frontier(search_mode: SearchMode) -> Direction {
+        match search_mode {
+            SearchMode::DepartAt => Direction::Outward,
+            SearchMode::ArriveBy => Direction::Inward,
+        }
+    }
+
+    pub(crate) fn reversed(self) -> Direction {
+        match self {
+            Direction::Outward => Direction::Inward,
+            Direction::Inward => Direction::Outward,
+        }
+    }
+}
+
+fn location_xy(city: &City, gtfs: &Gtfs1, stop_id: IdType) -> Option<[f64; 2]> {
+    let stop = gtfs.stops.get(&stop_id)?;
+    Some(projection::project_lng_lat(city, stop.longitude?, stop.latitude?))
+}
+
+/// Time to walk a pathway, split so it can be rescaled to the walking speed of a search
+#[derive(Clone, Copy, Debug, Default)]
+struct PathwayWalk {
+    /// Seconds that don't depend on the walking speed: traversal times, elevators and level changes
+    secs: f64,
+    /// Meters walked at the search's walking speed
+    length: f64,
+}
+
+impl PathwayWalk {
+    fn then(self, other: PathwayWalk) -> PathwayWalk {
+        PathwayWalk {
+            secs: self.secs + other.secs,
+            length: self.length + other.length,
+        }
+    }
+
+    fn secs(&self, walking_speed: f64) -> f64 {
+        self.secs + self.length / walking_speed
+    }
+}
+
+fn pathway_walk(city: &City, gtfs: &Gtfs1, pathway: &Pathway) -> PathwayWalk {
+    if let Some(secs) = pathway.traversal_time {
+        return PathwayWalk { secs: secs as f64, length: 0.0 };
+    }
+    if pathway.mode == PathwayMode::Elevator {
+        return PathwayWalk { secs: ELEVATOR_SECS, length: 0.0 };
+    }
+
+    let length = pathway.length.map(f64::from).or_else(|| {
+        let from = location_xy(city, gtfs, pathway.from_stop_id)?;
+        let to = location_xy(city, gtfs, pathway.to_stop_id)?;
+        Some(from.distance_2(&to).sqrt())
+    });
+    let levels = gtfs.level_change(pathway.from_stop_id, pathway.to_stop_id).abs();
+    PathwayWalk {
+        secs: levels * SECS_PER_LEVEL,
+        length: length.unwrap_or(0.0),
+    }
+}
+
+/// Walks from `start` to every location its pathways lead to. Routes are picked at the default
+/// walking speed, so searches walking at another speed only rescale them.
+fn station_walks(city: &City, gtfs: &Gtfs1, start: IdType, step_free: bool) -> FxHashMap<IdType, PathwayWalk> {
+    let speed = WalkingParameters::default().speed;
+    let mut walks = FxHashMap::default();
+    let mut queue = BinaryHeap::from([Reverse((Time(0.0), start))]);
+    walks.insert(start, PathwayWalk::default());
+    while let Some(Reverse((Time(secs), location))) = queue.pop() {
+        let walk = walks[&location];
+        if walk.secs(speed) < secs {
+            continue;
+        }
+
+        for pathway in gtfs.pathways_from(location) {
+            if step_free && !pathway.is_step_free() {
+                continue;
+            }
+            let next = pathway.to_stop_id;
+            let next_walk = walk.then(pathway_walk(city, gtfs, pathway));
+            let next_secs = next_walk.secs(speed);
+            if walks.get(&next).is_some_and(|best| best.secs(speed) <= next_secs) {
+                continue;
+            }
+            walks.insert(next, next_walk);
+            queue.push(Reverse((Time(next_secs), next)));
+        }
+    }
+    walks
+}
+
+/// Walks through every station with pathways, between each pair of locations linked by them.
+/// Computed once per network for both wheelchair users, who skip stairs and escalators, and others.
+#[derive(Debug, Default)]
+pub struct StationWalks {
+    /// [any pathway, step-free pathways only], each from location -> to location -> walk
+    walks: [FxHashMap<IdType, FxHashMap<IdType, PathwayWalk>>; 2],
+}
+
+impl StationWalks {
+    pub fn new(city: &City, gtfs: &Gtfs1) -> StationWalks {
+        let mut walks: [FxHashMap<_, _>; 2] = Default::default();
+        for (step_free, walks) in walks.iter_mut().enumerate() {
+            for location in gtfs.pathways.keys() {
+                walks.insert(*location, station_walks(city, gtfs, *location, step_free == 1));
+            }
+        }
+        StationWalks { walks }
+    }
+
+    fn walk(&self, from: IdType, to: IdType, step_free: bool) -> Option<&PathwayWalk> {
+        self.walks[step_free as usize].get(&from)?.get(&to)
+    }
+
+    /// Seconds to walk through the station from `from` to `to`, None if pathways don't link them
+    fn secs(&self, from: IdType, to: IdType, config: &Configuration) -> Option<f64> {
+        Some(self.walk(from, to, config.wheelchair)?.secs(config.walking.speed))
+    }
+}
+
+/// How a stop connects to the street. Stops of stations with pathways connect through the
+/// station's entrances, and other stops directly.
+pub(crate) struct StationAccess<'a> {
+    stop_id: IdType,
+    point: [f64; 2],
+    direction: Direction,
+    walks: &'a StationWalks,
+    /// Where the street is joined, with the seconds between there and the stop. Empty when
+    /// wheelchair users can't reach any of the station's entrances.
+    pub(crate) street_points: Vec<([f64; 2], f64)>,
+}
+
+impl<'a> StationAccess<'a> {
+    pub(crate) fn new(
+        city: &City,
+        gtfs: &Gtfs1,
+        walks: &'a StationWalks,
+        stop_id: IdType,
+        point: [f64; 2],
+        direction: Direction,
+        config: &Configuration,
+    ) -> StationAccess<'a> {
+        let between = |entrance: IdType, step_free: bool| match direction {
+            Direction::Outward => walks.walk(stop_id, entrance, step_free),
+            Direction::Inward => walks.walk(entrance, stop_id, step_free),
+        };
+        let entrances = gtfs
+            .stops
+            .get(&stop_id)
+            .and_then(|stop| gtfs.station_entrances.get(&stop.station_id?))
+            .map_or(&[][..], Vec::as_slice);
+        let street_points: Vec<_> = entrances
+            .iter()
+            .filter_map(|entrance| {
+                let walk = between(*entrance, config.wheelchair)?;
+                Some((location_xy(city, gtfs, *entrance)?, walk.secs(config.walking.speed)))
+            })
+            .collect();
+        // Pathways may only link platforms to each other, leaving the stop to be walked to directly.
+        // Wheelchair users still can't take the stairs out when they're the only way.
+        let linked_to_street = entrances.iter().any(|entrance| between(*entrance, false).is_some());
+        let street_points = if street_points.is_empty() && !linked_to_street {
+            vec![(point, 0.0)]
+        } else {
+            street_points
+        };
+
+        StationAccess {
+            stop_id,
+            point,
+            direction,
+            walks,
+            street_points,
+        }
+    }
+
+    /// Walking length and seconds between this stop and another one, through pathways if they share
+    /// a station, or else along the street between the closest entrances of both. None if either
+    /// stop can't be left for the street.
+    pub(crate) fn walk_to(
+        &self,
+        city: &City,
+        gtfs: &Gtfs1,
+        other: IdType,
+        other_point: [f64; 2],
+        config: &Configuration,
+    ) -> Option<(f64, f64)> {
+        let straight_length = self.point.distance_2(&other_point).sqrt();
+        let inside = match self.direction {
+            Direction::Outward => self.walks.secs(self.stop_id, other, config),
+            Direction::Inward => self.walks.secs(other, self.stop_id, config),
+        };
+        if let Some(secs) = inside {
+            return Some((straight_length, secs));
+        }
+
+        let other = StationAccess::new(
+            city,
+            gtfs,
+            self.walks,
+            other,
+            other_point,
+            self.direction.reversed(),
+            config,
+        );
+        let mut best = None;
+        for (point, secs) in &self.street_points {
+            for (other_point, other_secs) in &other.street_points {
+                let length = point.distance_2(other_point).sqrt();
+                let secs = secs + length / config.walking.straight_speed + other_secs;
+                if best.map_or(true, |(_, best_secs)| secs < best_secs) {
+                    best = Some((length, secs));
+                }
+            }
+        }
+        best
+    }
+}
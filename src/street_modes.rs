@@ -0,0 +1,85 @@
+use crate::bike_access::MAX_ORIGIN_SNAP_M;
+use crate::configuration::{Configuration, WalkingParameters};
+use crate::projection;
+use crate::reach_data::ReachData;
+use crate::road_structure::RoadStructure;
+use crate::time_to_reach::origin_trip;
+use rstar::PointDistance;
+
+/// Road nodes within this distance of a reached bike node take its cycling time, in meters
+const ROAD_SNAP_DISTANCE_M: f64 = 40.0;
+
+/// Walks along the road network from every origin for the whole search duration, without transit
+pub fn generate_walking_times(rs: &mut RoadStructure, config: Configuration) {
+    let search_mode = config.search_mode;
+    rs.trips_arena.set_search_mode(search_mode);
+    rs.walking = WalkingParameters {
+        max_walking_secs: config.duration_secs,
+        ..config.walking
+    };
+    rs.set_pareto(false);
+
+    for (origin, location) in config.origins().enumerate() {
+        let trip = origin_trip(rs.city(), &config, origin, location);
+        let point = trip.point;
+        let id = rs.trips_arena.alloc(trip);
+        rs.add_observation(
+            &point,
+            ReachData {
+                timestamp: search_mode.to_search_time(config.start_time),
+                cost: search_mode.to_search_time(config.start_time),
+                progress_trip_id: Some(id),
+                transfers: 0,
+                walking_length: 0.0,
+                origin: origin as u8,
+            },
+        );
+    }
+}
+
+/// Cycles along the bike network from every origin for the whole search duration, slower uphill.
+/// Times are copied onto the road nodes next to each reached bike node, so edge times match the
+/// other modes. Returns false if an origin is not near the bike network.
+pub fn generate_cycling_times(
+    rs: &mut RoadStructure,
+    graph: &bike::Graph,
+    config: Configuration,
+) -> bool {
+    let city = *rs.city();
+    rs.walking = config.walking;
+    rs.set_pareto(false);
+
+    for (origin, location) in config.origins().enumerate() {
+        let Some(isochrone) =
+            bike::isochrone(graph, &location.into(), config.duration_secs, MAX_ORIGIN_SNAP_M)
+        else {
+            return false;
+        };
+        let id = rs
+            .trips_arena
+            .alloc(origin_trip(&city, &config, origin, location));
+
+        for (_, point, secs) in isochrone.reached(graph) {
+            let xy = projection::project_lng_lat(&city, point.lon as f64, point.lat as f64);
+            for road_node in rs
+                .rs
+                .distance_nearest_nodes_to_point(xy, ROAD_SNAP_DISTANCE_M * ROAD_SNAP_DISTANCE_M)
+            {
+                let distance = road_node.distance_2(&xy).sqrt();
+                let time = config.start_time + secs + distance / config.walking.straight_speed;
+                rs.nb.set_best_time(
+                    road_node.data,
+                    ReachData {
+                        timestamp: time,
+                        cost: time,
+                        progress_trip_id: Some(id),
+                        transfers: 0,
+                        walking_length: 0.0,
+                        origin: origin as u8,
+                    },
+                );
+            }
+        }
+    }
+    true
+}
@@ -1,21 +1,34 @@
-use crate::configuration::Configuration;
-use crate::gtfs_processing::{RouteStopSequence, SpatialStopsWithTrips};
+use crate::configuration::{Configuration, SearchEngine, SearchMode};
+use crate::fares::NextLeg;
+use crate::stations::{Direction, StationAccess};
+use crate::raptor;
+use crate::realtime::{self, TripTimes};
+use crate::gtfs_processing::{RouteStopSequence, StopsData};
 use crate::in_progress_trip::InProgressTrip;
+use crate::network::Network;
 use crate::reach_data::ReachData;
 use crate::road_structure::RoadStructure;
 use crate::{
-    projection, BusPickupInfo, Gtfs1, TripsArena, MIN_TRANSFER_SECONDS, NULL_ID,
-    STRAIGHT_WALKING_SPEED, TRANSIT_EXIT_PENALTY,
+    projection, BusPickupInfo, TripsArena, MIN_TRANSFER_SECONDS, NULL_ID,
+    SECONDS_PER_DAY, TRANSIT_EXIT_PENALTY,
+};
+use gtfs_structure_2::gtfs_wrapper::{
+    Availability1, Stop, StopTime, Transfer, TransferType, Trip,
 };
-use gtfs_structure_2::gtfs_wrapper::StopTime;
 use gtfs_structure_2::IdType;
 
 use crate::agencies::City;
-use chrono::Utc;
+use chrono::{Days, NaiveDate};
 use id_arena::Id;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeSet;
 
 use crate::time::Time;
+use crate::web::LatLng;
+
+// GTFS trips belong to a service day but can run past 24:00:00. Besides the requested day, search
+// yesterday's trips still running after midnight and tomorrow's early trips.
+const SERVICE_DAY_OFFSETS: [i8; 3] = [-1, 0, 1];
 
 // #[derive(Debug, Default)]
 // pub struct TimeToReachRTree {
@@ -76,19 +89,95 @@ use crate::time::Time;
 //     // }
 // }
 
-pub fn generate_reach_times(
-    gtfs: &Gtfs1,
-    data: &SpatialStopsWithTrips,
-    rs: &mut RoadStructure,
-    config: Configuration,
-) {
-    const MAX_TRANSFERS: u8 = 4;
-    let location = config.location;
-    rs.trips_arena.add_to_explore(InProgressTrip {
+pub fn generate_reach_times(network: &Network, rs: &mut RoadStructure, config: Configuration) {
+    if config.engine == SearchEngine::Raptor {
+        return raptor::generate_reach_times(network, rs, config);
+    }
+
+    let search_mode = config.search_mode;
+    let search_start = search_mode.to_search_time(config.start_time);
+    rs.trips_arena.set_search_mode(search_mode);
+    rs.trips_arena.set_search_start(search_start);
+    rs.walking = config.walking;
+    rs.set_pareto(config.pareto_transfers);
+    rs.bike_access = config.bike_access.clone();
+    rs.realtime = config.realtime.clone();
+    rs.service_date = config.service_date;
+    rs.alerts_time = config.alerts_time;
+    for (origin, location) in config.origins().enumerate() {
+        let origin_trip = origin_trip(rs.city(), &config, origin, location);
+        let origin_id = rs.trips_arena.add_origin(origin_trip.clone());
+        for trip in bike_access_trips(rs.city(), network, &config, &origin_trip, origin_id) {
+            rs.trips_arena.add_origin(trip);
+        }
+    }
+
+    while let Some((item, id)) = rs.trips_arena.pop_front() {
+        let (frontier_stop, frontier_time) = item.frontier(search_mode);
+        let search_time = search_mode.to_search_time(frontier_time);
+        if search_time > search_start + config.duration_secs {
+            continue;
+        }
+        let cost = search_start + item.cost;
+        // The first boarding isn't a transfer
+        if item.total_transfers > config.max_transfers.saturating_add(1) {
+            continue;
+        }
+        if !rs.is_first_reacher_to_stop(
+            frontier_stop,
+            &item.point,
+            cost,
+            item.total_transfers,
+        ) {
+            continue;
+        }
+
+        let city = *rs.city();
+        let exit = StationAccess::new(
+            &city,
+            network.gtfs,
+            network.station_walks(),
+            frontier_stop,
+            item.point,
+            Direction::from_frontier(search_mode),
+            &config,
+        );
+        for (point, secs) in exit.street_points {
+            rs.add_observation(
+                &point,
+                ReachData {
+                    timestamp: search_time + TRANSIT_EXIT_PENALTY + secs,
+                    cost: cost + TRANSIT_EXIT_PENALTY + secs * config.walking.cost_weight,
+                    progress_trip_id: Some(id),
+                    transfers: item.total_transfers,
+                    walking_length: 0.0,
+                    origin: item.origin,
+                },
+            );
+        }
+        match search_mode {
+            SearchMode::DepartAt => {
+                explore_from_point(&city, network, item, id, &mut rs.trips_arena, &config)
+            }
+            SearchMode::ArriveBy => {
+                explore_backward_from_point(&city, network, item, id, &mut rs.trips_arena, &config)
+            }
+        }
+    }
+}
+
+// Starting point of the search at one of the origins
+pub(crate) fn origin_trip(
+    city: &City,
+    config: &Configuration,
+    origin: usize,
+    location: LatLng,
+) -> InProgressTrip {
+    InProgressTrip {
         trip_id: NULL_ID,
         boarding_time: config.start_time,
         exit_time: config.start_time,
-        point: projection::project_lng_lat(rs.city(), location.longitude, location.latitude),
+        point: projection::project_lng_lat(city, location.longitude, location.latitude),
         current_route: RouteStopSequence::default(),
         get_off_stop_id: NULL_ID,
         total_transfers: 0,
@@ -99,34 +188,54 @@ pub fn generate_reach_times(
         walking_length_m: 0.0,
         boarding_stop_time_idx: 0,
         get_off_stop_time_idx: 0,
-    }, config.transfer_cost);
+        service_day_offset: 0,
+        origin: origin as u8,
+        cost: 0.0,
+        fare: None,
+    }
+}
 
-    while let Some((item, id)) = rs.trips_arena.pop_front() {
-        if item.exit_time > config.start_time + config.duration_secs {
-            continue;
-        }
-        if item.total_transfers > MAX_TRANSFERS {
-            continue;
-        }
-        if !rs.is_first_reacher_to_stop(item.get_off_stop_id, &item.point, item.exit_time) {
-            continue;
-        }
+// Stops cycled to from `origin_trip`, as trips getting off there after parking the bike
+pub(crate) fn bike_access_trips(
+    city: &City,
+    network: &Network,
+    config: &Configuration,
+    origin_trip: &InProgressTrip,
+    origin_id: Id<InProgressTrip>,
+) -> Vec<InProgressTrip> {
+    let Some(bike_access) = config.bike_access.get(origin_trip.origin as usize) else {
+        return Vec::new();
+    };
 
-        rs.add_observation(
-            &item.point,
-            ReachData {
-                timestamp: item.exit_time + TRANSIT_EXIT_PENALTY,
-                progress_trip_id: Some(id),
-                transfers: item.total_transfers,
-                walking_length: 0.0,
-            },
-        );
-        let city = *rs.city();
-        explore_from_point(&city, gtfs, data, item, id, &mut rs.trips_arena, &config);
-    }
+    bike_access
+        .stops
+        .iter()
+        .filter(|access| is_stop_allowed(network.stop(access.stop_id), config))
+        .map(|access| InProgressTrip {
+            trip_id: NULL_ID,
+            boarding_time: origin_trip.exit_time,
+            exit_time: origin_trip.exit_time + access.secs,
+            point: projection::project_stop(city, network.stop(access.stop_id)),
+            current_route: RouteStopSequence::default(),
+            get_off_stop_id: access.stop_id,
+            total_transfers: 0,
+            previous_transfer: Some(origin_id),
+            boarding_stop_id: NULL_ID,
+            is_free_transfer: false,
+            walking_time: Time(0.0),
+            walking_length_m: 0.0,
+            boarding_stop_time_idx: 0,
+            get_off_stop_time_idx: 0,
+            service_day_offset: 0,
+            origin: origin_trip.origin,
+            // Cycling is weighed like time
+            cost: origin_trip.cost + access.secs,
+            fare: None,
+        })
+        .collect()
 }
 
-fn get_stop_from_stop_seq_no(stop_times: &[StopTime], stop_sequence_no: u16) -> (&StopTime, usize) {
+pub(crate) fn get_stop_from_stop_seq_no(stop_times: &[StopTime], stop_sequence_no: u16) -> (&StopTime, usize) {
     for i in 0..=stop_sequence_no as usize {
         if stop_times[i].stop_sequence == stop_sequence_no {
             return (&stop_times[i], i);
@@ -137,36 +246,83 @@ fn get_stop_from_stop_seq_no(stop_times: &[StopTime], stop_sequence_no: u16) ->
 
 fn all_stops_along_trip(
     city: &City,
-    gtfs: &Gtfs1,
-    trip_id: IdType,
-    start_sequence_no: u16,
+    network: &Network,
+    pickup: &BusPickupInfo,
     route_info: &RouteStopSequence,
     previous_transfer_id: Id<InProgressTrip>,
     transfers_remaining: u8,
     explore_queue: &mut TripsArena,
     transfer_walking_time: Time,
     transfer_walking_length: f64,
-    transfer_cost: u64
+    config: &Configuration,
 ) {
-    let is_free_transfer = explore_queue
-        .get_by_id(previous_transfer_id)
-        .is_free_transfer;
-    let stop_times = &gtfs.trips[&trip_id].stop_times;
-    let (boarding_stop, stop_time_index) = get_stop_from_stop_seq_no(stop_times, start_sequence_no);
+    let previous_trip = explore_queue.get_by_id(previous_transfer_id);
+    let is_free_transfer = previous_trip.is_free_transfer;
+    let origin = previous_trip.origin;
+    let trip_id = pickup.trip_id;
+    let day_shift = pickup.service_day_offset as f64 * SECONDS_PER_DAY;
+    let stop_times = &realtime::trip(network, config.realtime.as_deref(), trip_id).stop_times;
+    let times = TripTimes::new(
+        config.realtime.as_deref(),
+        trip_id,
+        shifted_service_date(config.service_date, pickup.service_day_offset),
+    );
+    let (boarding_stop, stop_time_index) =
+        get_stop_from_stop_seq_no(stop_times, pickup.stop_sequence_no);
+    // Already the realtime departure
+    let boarding_time = pickup.timestamp;
+
+    let mode_cost = config.cost.mode(&network.route(route_info.route_id).route_type);
+    let waiting_time = boarding_time - previous_trip.exit_time - transfer_walking_time;
+    let mut base_cost = previous_trip.cost
+        + transfer_walking_time.0 * config.walking.cost_weight
+        + waiting_time.0.max(0.0) * config.cost.wait_weight;
+    // Staying on the vehicle within a block isn't boarding again, and the previous leg already paid for getting off
+    if transfers_remaining != previous_trip.total_transfers {
+        base_cost += mode_cost.boarding_penalty + mode_cost.alighting_penalty;
+    }
+    let in_vehicle_weight = config.cost.in_vehicle_weight * mode_cost.weight;
+    // Only needed to price journeys against the max fare
+    let mut next_leg = config.max_fare.map(|max_fare| {
+        NextLeg::new(
+            network,
+            previous_trip,
+            transfers_remaining == previous_trip.total_transfers,
+            route_info.route_id,
+            boarding_stop.stop_id,
+            boarding_time,
+            SearchMode::DepartAt,
+            max_fare,
+        )
+    });
 
     for (_stops_travelled, st) in stop_times[stop_time_index + 1..].iter().enumerate() {
-        let stop = &gtfs.stops[&st.stop_id];
-        let point = projection::project_stop(city, stop);
-        if st.arrival_time.is_none() {
-            println!("Stop time arrival is none {}", st.stop_id.0);
+        if let Some(next_leg) = &mut next_leg {
+            next_leg.pass(network, st.stop_id);
         }
+        let stop = network.stop(st.stop_id);
+        if !is_stop_allowed(stop, config) || !is_route_stop_allowed(route_info, stop, config) {
+            continue;
+        }
+        let point = projection::project_stop(city, stop);
+        // Skipped by the realtime feed
+        let Some(timestamp) = times.arrival(st) else {
+            continue;
+        };
 
-        let timestamp = st.arrival_time.unwrap();
-
+        let exit_time = Time(timestamp as f64 + day_shift);
+        // Not a break: zone fares can be cheaper further along
+        let fare = match &mut next_leg {
+            Some(next_leg) => match next_leg.end_at(network, st.stop_id, exit_time) {
+                Some(fare) => Some(fare),
+                None => continue,
+            },
+            None => None,
+        };
         let current_inprogress_trip = InProgressTrip {
             trip_id,
-            boarding_time: Time(boarding_stop.arrival_time.unwrap() as f64),
-            exit_time: Time(timestamp as f64),
+            boarding_time,
+            exit_time,
             point,
             current_route: route_info.clone(),
             get_off_stop_id: st.stop_id,
@@ -178,9 +334,13 @@ fn all_stops_along_trip(
             walking_length_m: transfer_walking_length as f32,
             boarding_stop_time_idx: boarding_stop.index_of_stop_time,
             get_off_stop_time_idx: st.index_of_stop_time,
+            service_day_offset: pickup.service_day_offset,
+            origin,
+            cost: base_cost + (exit_time - boarding_time).0 * in_vehicle_weight,
+            fare,
         };
 
-        let id = explore_queue.add_to_explore(current_inprogress_trip, transfer_cost);
+        let id = explore_queue.add_to_explore(current_inprogress_trip, config.transfer_cost);
 
         if id.is_none() {
             break;
@@ -188,94 +348,509 @@ fn all_stops_along_trip(
     }
 }
 
-fn explore_from_point(
+fn all_stops_before_exit(
     city: &City,
-    gtfs: &Gtfs1,
-    data: &SpatialStopsWithTrips,
-    ip: InProgressTrip,
-    ip_id: Id<InProgressTrip>,
+    network: &Network,
+    dropoff: &BusPickupInfo,
+    route_info: &RouteStopSequence,
+    previous_transfer_id: Id<InProgressTrip>,
+    transfers_remaining: u8,
     explore_queue: &mut TripsArena,
+    transfer_walking_time: Time,
+    transfer_walking_length: f64,
     config: &Configuration,
 ) {
-    let today = Utc::now().date_naive();
+    let previous_trip = explore_queue.get_by_id(previous_transfer_id);
+    let is_free_transfer = previous_trip.is_free_transfer;
+    let origin = previous_trip.origin;
+    let trip_id = dropoff.trip_id;
+    let day_shift = dropoff.service_day_offset as f64 * SECONDS_PER_DAY;
+    let stop_times = &network.trip(trip_id).stop_times;
+    let (exit_stop, stop_time_index) = get_stop_from_stop_seq_no(stop_times, dropoff.stop_sequence_no);
+    let exit_time = Time(exit_stop.arrival_time.unwrap() as f64 + day_shift);
 
-    let mut routes_already_taken = FxHashSet::from_iter([ip.current_route.clone()]);
-    let current_trip = &gtfs.trips.get(&ip.trip_id);
+    let mode_cost = config.cost.mode(&network.route(route_info.route_id).route_type);
+    let waiting_time = previous_trip.boarding_time - exit_time - transfer_walking_time;
+    let mut base_cost = previous_trip.cost
+        + transfer_walking_time.0 * config.walking.cost_weight
+        + waiting_time.0.max(0.0) * config.cost.wait_weight;
+    // Staying on the vehicle within a block isn't getting off, and the next leg already paid for boarding
+    if transfers_remaining != previous_trip.total_transfers {
+        base_cost += mode_cost.boarding_penalty + mode_cost.alighting_penalty;
+    }
+    let in_vehicle_weight = config.cost.in_vehicle_weight * mode_cost.weight;
+    // Only needed to price journeys against the max fare
+    let mut next_leg = config.max_fare.map(|max_fare| {
+        NextLeg::new(
+            network,
+            previous_trip,
+            transfers_remaining == previous_trip.total_transfers,
+            route_info.route_id,
+            exit_stop.stop_id,
+            exit_time,
+            SearchMode::ArriveBy,
+            max_fare,
+        )
+    });
 
-    for (stop, distance) in data.0.nearest_neighbor_iter_with_distance_2(&ip.point) {
-        if distance > 800.0 * 800.0 {
-            // Exceeds the walking threshold.
+    // Walk the trip backwards: every earlier stop is a place we could have boarded
+    for st in stop_times[..stop_time_index].iter().rev() {
+        if let Some(next_leg) = &mut next_leg {
+            next_leg.pass(network, st.stop_id);
+        }
+        let stop = network.stop(st.stop_id);
+        if !is_stop_allowed(stop, config) || !is_route_stop_allowed(route_info, stop, config) {
+            continue;
+        }
+        let point = projection::project_stop(city, stop);
+
+        let boarding_time = Time(st.departure_time.unwrap() as f64 + day_shift);
+        // Not a break: zone fares can be cheaper further along
+        let fare = match &mut next_leg {
+            Some(next_leg) => match next_leg.end_at(network, st.stop_id, boarding_time) {
+                Some(fare) => Some(fare),
+                None => continue,
+            },
+            None => None,
+        };
+        let current_inprogress_trip = InProgressTrip {
+            trip_id,
+            boarding_time,
+            exit_time,
+            point,
+            current_route: route_info.clone(),
+            get_off_stop_id: exit_stop.stop_id,
+            boarding_stop_id: st.stop_id,
+            total_transfers: transfers_remaining,
+            previous_transfer: Some(previous_transfer_id),
+            is_free_transfer,
+            walking_time: transfer_walking_time,
+            walking_length_m: transfer_walking_length as f32,
+            boarding_stop_time_idx: st.index_of_stop_time,
+            get_off_stop_time_idx: exit_stop.index_of_stop_time,
+            service_day_offset: dropoff.service_day_offset,
+            origin,
+            cost: base_cost + (exit_time - boarding_time).0 * in_vehicle_weight,
+            fare,
+        };
+
+        let id = explore_queue.add_to_explore(current_inprogress_trip, config.transfer_cost);
+
+        if id.is_none() {
             break;
         }
+    }
+}
 
-        let stop_d = &stop.data;
+// Stops with unknown accessibility are kept, otherwise most feeds would have no step-free service at all
+fn is_stop_accessible(stop: &Stop, config: &Configuration) -> bool {
+    !config.wheelchair || stop.wheelchair_boarding != Availability1::NotAvailable
+}
 
-        let transfer_walking_length = distance.sqrt();
-        let time_to_stop = transfer_walking_length / STRAIGHT_WALKING_SPEED;
-        let this_timestamp = ip.exit_time + time_to_stop + MIN_TRANSFER_SECONDS;
+fn is_trip_accessible(trip: &Trip, config: &Configuration) -> bool {
+    !config.wheelchair || trip.wheelchair_accessible != Availability1::NotAvailable
+}
 
-        // Search for route pickup on or after the starting_timestamp
-        for (route_info, route_pickup) in stop_d.trips_with_time.0.iter() {
-            if routes_already_taken.contains(route_info) {
-                continue;
-            }
+/// Whether the stop can be boarded or left at, being accessible and not excluded
+pub(crate) fn is_stop_allowed(stop: &Stop, config: &Configuration) -> bool {
+    is_stop_accessible(stop, config) && !config.exclusions.stops.contains(&stop.id)
+}
+
+fn is_trip_allowed(trip: &Trip, config: &Configuration) -> bool {
+    is_trip_accessible(trip, config) && !config.exclusions.trips.contains(&trip.id)
+}
 
-            let is_valid_agency = config.agency_ids.contains(&route_info.route_id.0);
+/// Whether the route can be boarded or left at the stop, which alerts can close for one route only
+pub(crate) fn is_route_stop_allowed(route_info: &RouteStopSequence, stop: &Stop, config: &Configuration) -> bool {
+    !config.exclusions.route_stops.contains(&(route_info.route_id, stop.id))
+}
 
-            let this_route = &gtfs.routes[&route_info.route_id];
+pub(crate) fn is_route_allowed(network: &Network, route_info: &RouteStopSequence, config: &Configuration) -> bool {
+    let is_valid_agency = config.agency_ids.contains(&route_info.route_id.0)
+        && !config.exclusions.routes.contains(&route_info.route_id);
 
-            if !is_valid_agency {
-                continue;
-            }
+    let this_route = network.route(route_info.route_id);
 
-            if !(config.modes.is_empty() || config.modes.contains(&this_route.route_type)) {
-                continue;
-            }
+    is_valid_agency && (config.modes.is_empty() || config.modes.contains(&this_route.route_type))
+}
+
+// Service dates matching SERVICE_DAY_OFFSETS
+pub(crate) fn service_dates(config: &Configuration) -> [NaiveDate; 3] {
+    [
+        config.service_date.pred_opt().unwrap(),
+        config.service_date,
+        config.service_date.succ_opt().unwrap(),
+    ]
+}
+
+// Service date of a pickup shifted by `day_offset` from the searched `service_date`
+pub(crate) fn shifted_service_date(service_date: NaiveDate, day_offset: i8) -> NaiveDate {
+    let days = Days::new(day_offset.unsigned_abs() as u64);
+    if day_offset < 0 {
+        service_date - days
+    } else {
+        service_date + days
+    }
+}
+
+// Transfers count after going from `ip` onto the trip of `bus`
+fn transfers_after_taking(network: &Network, ip: &InProgressTrip, bus: &BusPickupInfo, config: &Configuration) -> u8 {
+    let realtime = config.realtime.as_deref();
+    let this_trip = realtime::trip(network, realtime, bus.trip_id);
+    let current_trip = realtime::find_trip(network, realtime, ip.trip_id);
+
+    // Blocks only continue on the same service day
+    let is_free_tranfer = this_trip.block_id.is_some()
+        && ip.service_day_offset == bus.service_day_offset
+        && this_trip.block_id.as_ref() == current_trip.and_then(|a| a.block_id.as_ref());
+
+    if is_free_tranfer {
+        ip.total_transfers
+    } else {
+        ip.total_transfers + 1
+    }
+}
+
+// Earliest pickup of a route at or after `time` over all searched service days. With a realtime
+// overlay, pickups of added trips are searched too and the pickup's timestamp is its realtime departure.
+pub(crate) fn earliest_pickup(
+    network: &Network,
+    route_pickup: &BTreeSet<BusPickupInfo>,
+    added_pickup: Option<&BTreeSet<BusPickupInfo>>,
+    time: Time,
+    search_end: Time,
+    service_dates: &[NaiveDate; 3],
+    config: &Configuration,
+    can_board: impl Fn(&BusPickupInfo) -> bool,
+) -> Option<BusPickupInfo> {
+    let realtime = config.realtime.as_deref();
+    // Pickups are ordered by scheduled departure, which delays move by at most this much
+    let (max_delay, max_early) = realtime.map_or((0.0, 0.0), |realtime| (realtime.max_delay, realtime.max_early));
 
+    let mut best_pickup: Option<BusPickupInfo> = None;
+    for pickups in std::iter::once(route_pickup).chain(added_pickup) {
+        for (day_offset, service_date) in SERVICE_DAY_OFFSETS.into_iter().zip(service_dates) {
             let starting_buspickup = BusPickupInfo {
-                timestamp: this_timestamp,
+                timestamp: Time(time.0 - max_delay - day_offset as f64 * SECONDS_PER_DAY),
                 stop_sequence_no: 0,
                 trip_id: NULL_ID,
+                service_day_offset: 0,
             };
 
-            for next_bus in route_pickup.range(starting_buspickup..) {
-                debug_assert!(next_bus.timestamp >= this_timestamp);
+            for next_bus in pickups.range(starting_buspickup..) {
+                let mut next_bus = next_bus.shifted_by_days(day_offset);
+                let earliest_departure = Time(next_bus.timestamp.0 - max_early);
 
-                let this_trip = &gtfs.trips[&next_bus.trip_id];
+                if earliest_departure > search_end
+                    || best_pickup
+                        .as_ref()
+                        .is_some_and(|best| best.timestamp <= earliest_departure)
+                {
+                    break;
+                }
 
-                // If the service runs today
-                if !gtfs.calendar.runs_on_date(this_trip.service_id, today) {
+                let this_trip = realtime::trip(network, realtime, next_bus.trip_id);
+                if !is_trip_allowed(this_trip, config) {
                     continue;
                 }
 
-                let is_free_tranfer = this_trip.block_id.is_some()
-                    && this_trip.block_id.as_ref()
-                        == current_trip.and_then(|a| a.block_id.as_ref());
+                // If the service runs on the service day of this pickup
+                if !realtime::runs_on_date(network, realtime, this_trip, *service_date) {
+                    continue;
+                }
 
-                let transfers_remaining = if is_free_tranfer {
-                    ip.total_transfers
-                } else {
-                    ip.total_transfers + 1
+                let Some(departure) = realtime::pickup_departure(realtime, this_trip, &next_bus, *service_date)
+                else {
+                    continue;
                 };
+                next_bus.timestamp = departure;
+                if departure < time
+                    || departure > search_end
+                    || best_pickup.as_ref().is_some_and(|best| best.timestamp <= departure)
+                {
+                    continue;
+                }
 
-                if explore_queue.should_explore(next_bus) {
-                    all_stops_along_trip(
-                        city,
-                        gtfs,
-                        next_bus.trip_id,
-                        next_bus.stop_sequence_no,
-                        route_info,
-                        ip_id,
-                        transfers_remaining,
-                        explore_queue,
-                        Time(time_to_stop),
-                        transfer_walking_length,
-                        config.transfer_cost
-                    );
-                    routes_already_taken.insert(route_info.clone());
-                    break;
+                // Without delays, later pickups can't be better and the next iteration breaks
+                if can_board(&next_bus) {
+                    best_pickup = Some(next_bus);
                 }
             }
         }
     }
+    best_pickup
+}
+
+pub(crate) struct TransferCandidate<'a> {
+    pub(crate) stop: &'a StopsData,
+    pub(crate) walking_length: f64,
+    pub(crate) walking_time: f64,
+    // Seconds between getting off one vehicle and being able to board the next
+    pub(crate) transfer_time: f64,
+}
+
+impl<'a> TransferCandidate<'a> {
+    // None if transfers.txt forbids this transfer
+    fn new(
+        stop: &'a StopsData,
+        walking_length: f64,
+        walking_time: f64,
+        rule: Option<&Transfer>,
+    ) -> Option<Self> {
+        let default_transfer_time = walking_time + MIN_TRANSFER_SECONDS;
+        let transfer_time = match rule {
+            None => default_transfer_time,
+            Some(rule) => match rule.transfer_type {
+                TransferType::Recommended => default_transfer_time,
+                // The departing vehicle waits for the arriving one, but we still have to walk to it
+                TransferType::Timed => walking_time,
+                TransferType::MinTime => rule
+                    .min_transfer_time
+                    .map(|a| a as f64)
+                    .unwrap_or(default_transfer_time),
+                TransferType::Impossible => return None,
+            },
+        };
+        Some(TransferCandidate {
+            stop,
+            walking_length,
+            walking_time,
+            transfer_time,
+        })
+    }
+}
+
+// Stops around the frontier of `ip` we can transfer to (or from, when arriving by), honouring the
+// transfer rules of transfers.txt. Explicit transfers can reach stops beyond the walking threshold.
+// Stations with pathways are walked through their pathway graph and left by their entrances.
+pub(crate) fn transfer_candidates<'a>(
+    city: &City,
+    network: &Network<'a>,
+    ip: &InProgressTrip,
+    search_mode: SearchMode,
+    config: &Configuration,
+) -> Vec<TransferCandidate<'a>> {
+    let walking = &config.walking;
+    let (frontier_stop, _) = ip.frontier(search_mode);
+    let direction = Direction::from_frontier(search_mode);
+    let frontier = StationAccess::new(
+        city,
+        network.gtfs,
+        network.station_walks(),
+        frontier_stop,
+        ip.point,
+        direction,
+        config,
+    );
+    // Other stop of the transfer -> transfer rule
+    let mut rules: FxHashMap<IdType, &Transfer> = match search_mode {
+        // The origin isn't a stop, so no rules apply
+        _ if ip.trip_id == NULL_ID => FxHashMap::default(),
+        SearchMode::DepartAt => network
+            .gtfs
+            .transfers_from(frontier_stop)
+            .map(|t| (t.to_stop_id, t))
+            .collect(),
+        SearchMode::ArriveBy => network
+            .gtfs
+            .transfers_to(frontier_stop)
+            .map(|t| (t.from_stop_id, t))
+            .collect(),
+    };
+
+    let mut candidates = Vec::new();
+    for (stop, _) in network.stops_near(&ip.point, walking.max_transfer_distance_m) {
+        let rule = rules.remove(&stop.data.stop_id);
+        let Some((walking_length, walking_time)) =
+            frontier.walk_to(city, network.gtfs, stop.data.stop_id, *stop.geom(), config)
+        else {
+            continue;
+        };
+        candidates.extend(TransferCandidate::new(
+            &stop.data,
+            walking_length,
+            walking_time,
+            rule,
+        ));
+    }
+
+    for (stop_id, rule) in rules {
+        let Some(stop) = network.get_stop(city, stop_id) else {
+            continue;
+        };
+        let Some((walking_length, walking_time)) = frontier.walk_to(city, network.gtfs, stop_id, *stop.geom(), config)
+        else {
+            continue;
+        };
+        candidates.extend(TransferCandidate::new(
+            &stop.data,
+            walking_length,
+            walking_time,
+            Some(rule),
+        ));
+    }
+    candidates
+}
+
+fn explore_from_point(
+    city: &City,
+    network: &Network,
+    ip: InProgressTrip,
+    ip_id: Id<InProgressTrip>,
+    explore_queue: &mut TripsArena,
+    config: &Configuration,
+) {
+    let mut routes_already_taken = FxHashSet::from_iter([ip.current_route.clone()]);
+    let search_end = config.start_time + config.duration_secs;
+    let service_dates = service_dates(config);
+
+    for candidate in transfer_candidates(city, network, &ip, SearchMode::DepartAt, config) {
+        let stop_d = candidate.stop;
+        if !is_stop_allowed(network.stop(stop_d.stop_id), config) {
+            continue;
+        }
+        let this_timestamp = ip.exit_time + candidate.transfer_time;
+
+        // Search for route pickup on or after the starting_timestamp
+        for (route_info, route_pickup, added_pickup) in realtime::route_pickups(stop_d, config.realtime.as_deref()) {
+            if routes_already_taken.contains(route_info) {
+                continue;
+            }
+
+            if !is_route_allowed(network, route_info, config)
+                || !is_route_stop_allowed(route_info, network.stop(stop_d.stop_id), config)
+            {
+                continue;
+            }
+
+            let best_pickup = earliest_pickup(
+                network,
+                route_pickup,
+                added_pickup,
+                this_timestamp,
+                search_end,
+                &service_dates,
+                config,
+                |next_bus| {
+                    let boardings = transfers_after_taking(network, &ip, next_bus, config);
+                    explore_queue.should_explore(next_bus, boardings)
+                },
+            );
+
+            if let Some(next_bus) = best_pickup {
+                let transfers_remaining = transfers_after_taking(network, &ip, &next_bus, config);
+
+                explore_queue.mark_trip_taken(&next_bus, transfers_remaining);
+                all_stops_along_trip(
+                    city,
+                    network,
+                    &next_bus,
+                    route_info,
+                    ip_id,
+                    transfers_remaining,
+                    explore_queue,
+                    Time(candidate.walking_time),
+                    candidate.walking_length,
+                    config,
+                );
+                routes_already_taken.insert(route_info.clone());
+            }
+        }
+    }
+}
+
+// Mirror of explore_from_point for arrive-by searches. `ip` is boarded at `ip.point`, so we look
+// for the latest vehicle dropping us off nearby in time to walk over and board it.
+fn explore_backward_from_point(
+    city: &City,
+    network: &Network,
+    ip: InProgressTrip,
+    ip_id: Id<InProgressTrip>,
+    explore_queue: &mut TripsArena,
+    config: &Configuration,
+) {
+    let mut routes_already_taken = FxHashSet::from_iter([ip.current_route.clone()]);
+    let search_begin = Time(config.start_time.0 - config.duration_secs);
+    let service_dates = service_dates(config);
+
+    for candidate in transfer_candidates(city, network, &ip, SearchMode::ArriveBy, config) {
+        let stop_d = candidate.stop;
+        if !is_stop_allowed(network.stop(stop_d.stop_id), config) {
+            continue;
+        }
+        let this_timestamp = Time(ip.boarding_time.0 - candidate.transfer_time);
+
+        // Search for route drop off on or before this_timestamp. Pickup times are departures, which are
+        // never before arrivals, so a vehicle still dwelling at this_timestamp isn't used.
+        for (route_info, route_pickup) in stop_d.trips_with_time.0.iter() {
+            if routes_already_taken.contains(route_info) {
+                continue;
+            }
+
+            if !is_route_allowed(network, route_info, config)
+                || !is_route_stop_allowed(route_info, network.stop(stop_d.stop_id), config)
+            {
+                continue;
+            }
+
+            // Latest drop off of this route over all searched service days
+            let mut best_dropoff: Option<BusPickupInfo> = None;
+            for (day_offset, service_date) in SERVICE_DAY_OFFSETS.into_iter().zip(service_dates) {
+                let ending_buspickup = BusPickupInfo {
+                    timestamp: Time(this_timestamp.0 - day_offset as f64 * SECONDS_PER_DAY),
+                    stop_sequence_no: u16::MAX,
+                    trip_id: NULL_ID,
+                    service_day_offset: 0,
+                };
+
+                for prev_bus in route_pickup.range(..=ending_buspickup).rev() {
+                    let prev_bus = prev_bus.shifted_by_days(day_offset);
+                    debug_assert!(prev_bus.timestamp <= this_timestamp);
+
+                    if prev_bus.timestamp < search_begin
+                        || best_dropoff
+                            .as_ref()
+                            .is_some_and(|best| best.timestamp >= prev_bus.timestamp)
+                    {
+                        break;
+                    }
+
+                    let this_trip = network.trip(prev_bus.trip_id);
+                    if !is_trip_allowed(this_trip, config) {
+                        continue;
+                    }
+
+                    // If the service runs on the service day of this drop off
+                    if !network.runs_on_date(this_trip.service_id, service_date) {
+                        continue;
+                    }
+
+                    let boardings = transfers_after_taking(network, &ip, &prev_bus, config);
+                    if explore_queue.should_explore(&prev_bus, boardings) {
+                        best_dropoff = Some(prev_bus);
+                        break;
+                    }
+                }
+            }
+
+            if let Some(prev_bus) = best_dropoff {
+                let transfers_remaining = transfers_after_taking(network, &ip, &prev_bus, config);
+
+                explore_queue.mark_trip_taken(&prev_bus, transfers_remaining);
+                all_stops_before_exit(
+                    city,
+                    network,
+                    &prev_bus,
+                    route_info,
+                    ip_id,
+                    transfers_remaining,
+                    explore_queue,
+                    Time(candidate.walking_time),
+                    candidate.walking_length,
+                    config,
+                );
+                routes_already_taken.insert(route_info.clone());
+            }
+        }
+    }
 }
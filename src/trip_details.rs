@@ -1,16 +1,25 @@
+use crate::configuration::{AccessMode, SearchEngine, SearchMode, TravelMode};
+use crate::fares::journey_fare;
 use crate::formatter::get_route_mode;
+use crate::realtime::{self, TripTimes};
+use crate::time_to_reach::shifted_service_date;
 use crate::web::RequestId;
 use crate::web_app_data::AllAppData;
-use crate::{time_to_point, LatLng, NULL_ID, WALKING_SPEED};
+use crate::{time_to_point, LatLng, NULL_ID, SECONDS_PER_DAY};
+use chrono::NaiveDate;
 use geo_types::Coord;
 use geojson::PointType;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct CalculateRequest {
+    // Replaced by each destination in commute requests
+    #[serde(default)]
     pub latitude: f64,
+    #[serde(default)]
     pub longitude: f64,
     pub agencies: Vec<String>,
     pub modes: Vec<String>,
@@ -25,12 +34,141 @@ pub struct CalculateRequest {
     pub previous_request_id: Option<RequestId>,
 
     #[serde(rename = "transferPenaltySecs")]
-    pub transfer_cost_secs: Option<u64>
+    pub transfer_cost_secs: Option<u64>,
+
+    /// Service day in the city's local time, formatted as YYYY-MM-DD. Defaults to today.
+    #[serde(rename = "serviceDate")]
+    pub service_date: Option<NaiveDate>,
+
+    /// IANA timezone name (e.g. "America/Toronto"). Optional, and must be the city's timezone if set.
+    pub timezone: Option<String>,
+
+    /// "departAt" (default) or "arriveBy". When arriving by, `startTime` is the arrival deadline,
+    /// the coordinates are the destination, and edge times are the latest departure times.
+    #[serde(rename = "searchMode", default)]
+    pub search_mode: SearchMode,
+
+    /// If set, evaluates every departure minute of this window and returns travel time statistics per edge
+    #[serde(rename = "departureWindowSecs")]
+    pub departure_window_secs: Option<u64>,
+
+    /// Step-free routing: skips stops and trips marked as not wheelchair accessible
+    #[serde(default)]
+    pub wheelchair: bool,
+
+    /// Walking speed along roads in m/s. Defaults to 1.42.
+    #[serde(rename = "walkingSpeed")]
+    pub walking_speed: Option<f64>,
+
+    /// Maximum straight-line distance walked to reach a stop, in meters. Defaults to 800.
+    #[serde(rename = "maxTransferWalkMeters")]
+    pub max_transfer_walk_m: Option<f64>,
+
+    /// Maximum time walked along roads from the origin or a stop. Defaults to 24 minutes.
+    #[serde(rename = "maxWalkingSecs")]
+    pub max_walking_secs: Option<f64>,
+
+    /// Road nodes within this distance of the origin or a stop are walked to directly, in meters. Defaults to 100.
+    #[serde(rename = "snapDistanceMeters")]
+    pub snap_distance_m: Option<f64>,
+
+    /// Maximum number of transfers. Defaults to 3.
+    #[serde(rename = "maxTransfers")]
+    pub max_transfers: Option<u8>,
+
+    /// Also returns edge times using at most 0, 1, 2 and any number of transfers
+    #[serde(rename = "paretoTransfers", default)]
+    pub pareto_transfers: bool,
+
+    /// More origins besides `latitude`/`longitude`. Edges are reached from whichever origin is fastest.
+    #[serde(rename = "extraOrigins", default)]
+    pub extra_origins: Vec<LatLng>,
+
+    /// "heuristic" (default) or "raptor"
+    #[serde(default)]
+    pub engine: SearchEngine,
+
+    /// "transit" (default), or "walk" and "bike" to only walk or cycle for the whole search
+    #[serde(rename = "travelMode", default)]
+    pub travel_mode: TravelMode,
+
+    /// "walk" (default) or "bike" to cycle from the origin to the first stop
+    #[serde(rename = "accessMode", default)]
+    pub access_mode: AccessMode,
+
+    /// Maximum time cycled to the first stop. Defaults to 15 minutes.
+    #[serde(rename = "maxCyclingSecs")]
+    pub max_cycling_secs: Option<f64>,
+
+    /// Minimizes a generalized cost instead of the travel time. Edge costs are then returned too.
+    #[serde(rename = "costModel")]
+    pub cost_model: Option<CostModelRequest>,
+
+    /// Stops exploring journeys that cost more than this, in the currency of the city's feeds
+    #[serde(rename = "maxFare")]
+    pub max_fare: Option<f64>,
+
+    /// Routes, stops, trips and an area the search doesn't use
+    #[serde(default)]
+    pub exclusions: ExclusionsRequest,
+
+    /// Applies delays, cancellations and added trips from the realtime feeds. Only for departAt searches.
+    #[serde(default)]
+    pub realtime: bool,
+
+    /// ID of a what-if scenario to search, e.g. with a proposed line
+    pub scenario: Option<String>,
+}
+
+/// A stop, route or trip by its ID in the feed of an agency
+#[derive(Deserialize, Clone)]
+pub struct FeedIdRequest {
+    /// Agency short name, as in `agencies`
+    pub agency: String,
+    pub id: String,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExclusionsRequest {
+    #[serde(default)]
+    pub routes: Vec<FeedIdRequest>,
+    /// Vehicles still run through these stops, but nobody gets on or off
+    #[serde(default)]
+    pub stops: Vec<FeedIdRequest>,
+    #[serde(default)]
+    pub trips: Vec<FeedIdRequest>,
+    /// Stops inside this polygon are excluded too. The first vertex doesn't need to be repeated.
+    pub avoid_area: Option<Vec<LatLng>>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModeCostRequest {
+    /// Multiplies the in-vehicle weight for this mode. Defaults to 1.
+    pub weight: Option<f64>,
+    pub boarding_penalty_secs: Option<f64>,
+    pub alighting_penalty_secs: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CostModelRequest {
+    /// Each weight defaults to 1
+    pub walk_weight: Option<f64>,
+    pub wait_weight: Option<f64>,
+    pub in_vehicle_weight: Option<f64>,
+    /// Mode name (as in `modes`) -> weight and penalties
+    #[serde(default)]
+    pub modes: BTreeMap<String, ModeCostRequest>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct TripDetailsInner {
     time: f64,
+    /// When the vehicle arrives at and departs from the stop. They differ when it dwells there.
+    arrival: f64,
+    departure: f64,
     line: String,
     stop: String,
 }
@@ -42,6 +180,8 @@ struct TripDetailsTransit {
     mode: &'static str,
     boarding: TripDetailsInner,
     exit: TripDetailsInner,
+    /// Realtime alerts about the route, the trip or the stops ridden through, such as closures nearby
+    alerts: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -50,11 +190,20 @@ struct TripDetailsWalking {
     length: f32,
 }
 
+#[derive(Serialize)]
+struct TripDetailsCycling {
+    time: f64,
+    length: f64,
+    /// Elevation in meters along the ride, one per point of its path
+    elevation: Vec<f32>,
+}
+
 #[derive(Serialize)]
 #[serde(tag = "method")]
 enum TripDetails {
     Transit(TripDetailsTransit),
     Walking(TripDetailsWalking),
+    Cycling(TripDetailsCycling),
 }
 
 #[derive(Deserialize)]
@@ -73,6 +222,7 @@ pub fn get_trip_details(
 ) -> Result<warp::reply::Json, &'static str> {
     let latlng = req.latlng;
     let city = req.request_id.city;
+    let bikegraph = &ad.bikegraph;
     let ad = ad.ads.get(&city).ok_or("Provided city not valid")?;
 
     ad.rs_list
@@ -84,11 +234,12 @@ pub fn get_trip_details(
     let rs_option = rs_list.get(req.request_id.rs_list_index);
 
     let rs = rs_option.ok_or("Invalid -- request ID not found")?;
+    let network = ad.network(rs.scenario.as_deref()).unwrap();
 
     let formatter = time_to_point(
         rs,
         &rs.trips_arena,
-        &ad.gtfs,
+        network,
         [latlng.latitude, latlng.longitude],
         true,
     );
@@ -97,7 +248,7 @@ pub fn get_trip_details(
 
     let mut details_list = Vec::new();
 
-    let final_walking_time = formatter.final_walking_length as f64 / WALKING_SPEED;
+    let final_walking_time = formatter.final_walking_length as f64 / rs.walking.speed;
     if final_walking_time >= 40.0 {
         details_list.push(TripDetails::Walking(TripDetailsWalking {
             time: final_walking_time,
@@ -139,11 +290,33 @@ pub fn get_trip_details(
             continue;
         }
 
-        let route = &ad.gtfs.routes[&trip.current_route.route_id];
-        let boarding_stop = &ad.gtfs.stops[&trip.boarding_stop_id];
-        let exit_stop = &ad.gtfs.stops[&trip.get_off_stop_id];
-
-        let mode = get_route_mode(&ad.gtfs, trip);
+        let route = network.route(trip.current_route.route_id);
+        let boarding_stop = network.stop(trip.boarding_stop_id);
+        let exit_stop = network.stop(trip.get_off_stop_id);
+
+        let mode = get_route_mode(&network, trip);
+        let ridden_trip = realtime::trip(&network, formatter.realtime, trip.trip_id);
+        let stop_times = &ridden_trip.stop_times;
+        let service_date = shifted_service_date(rs.service_date, trip.service_day_offset);
+        let times = TripTimes::new(formatter.realtime, trip.trip_id, service_date);
+        let day_shift = trip.service_day_offset as f64 * SECONDS_PER_DAY;
+        let scheduled = |idx: usize| {
+            let st = &stop_times[idx];
+            // Stops skipped by the realtime feed are never boarded or left at
+            let (arrival, departure) = times.at(st).unwrap();
+            (arrival as f64 + day_shift, departure as f64 + day_shift)
+        };
+        let (boarding_arrival, boarding_departure) = scheduled(trip.boarding_stop_time_idx);
+        let (exit_arrival, exit_departure) = scheduled(trip.get_off_stop_time_idx);
+
+        let ridden = &stop_times[trip.boarding_stop_time_idx..=trip.get_off_stop_time_idx];
+        let alerts = formatter
+            .realtime
+            .into_iter()
+            .flat_map(|realtime| realtime.active_alerts(rs.alerts_time))
+            .filter(|alert| alert.affects_ride(ridden_trip, ridden))
+            .map(|alert| alert.header.clone())
+            .collect();
 
         // Vary line-width based on how advanced the mode is
         let line_width = match mode {
@@ -168,14 +341,19 @@ pub fn get_trip_details(
             text_color: route.text_color.clone(),
             boarding: TripDetailsInner {
                 time: trip.boarding_time.0,
+                arrival: boarding_arrival,
+                departure: boarding_departure,
                 line: route.short_name.clone(),
                 stop: boarding_stop.name.clone(),
             },
             exit: TripDetailsInner {
                 time: trip.exit_time.0,
+                arrival: exit_arrival,
+                departure: exit_departure,
                 line: route.short_name.clone(),
                 stop: exit_stop_msg,
             },
+            alerts,
         }));
 
         if trip.walking_time.0 >= 30.0 {
@@ -189,7 +367,28 @@ pub fn get_trip_details(
         has_free_transfer_from_prev = trip.is_free_transfer;
     }
 
-    details_list.reverse();
+    let bike_leg = formatter.trips.iter().find(|trip| trip.is_bike_access()).and_then(|trip| {
+        let leg = rs.bike_access[trip.origin as usize].leg_to(bikegraph, trip.get_off_stop_id)?;
+        Some((trip, leg))
+    });
+    if let Some((trip, leg)) = bike_leg {
+        details_list.push(TripDetails::Cycling(TripDetailsCycling {
+            time: (trip.exit_time - trip.boarding_time).0,
+            length: leg.length_m,
+            elevation: leg.elevations,
+        }));
+
+        let coordinates: Vec<PointType> = leg.coordinates.iter().map(|c| c.to_vec()).collect();
+        let mut feature = geojson::Feature::from(geojson::Value::LineString(coordinates));
+        feature.set_property("mode", "bike");
+        features.push(feature);
+    }
+
+    // Departing at a time, the trips are traced back from the destination to the origin.
+    // Arriving by a time, they are traced from the origin forwards to the destination.
+    if rs.trips_arena.search_mode() == SearchMode::DepartAt {
+        details_list.reverse();
+    }
 
     features.extend(features_points);
     let geojson = geojson::FeatureCollection {
@@ -200,7 +399,13 @@ pub fn get_trip_details(
 
     let response = json!({
         "details": details_list,
-        "path": geojson
+        "path": geojson,
+        // Index of the origin this path starts from
+        "origin": formatter.trips.first().map(|trip| trip.origin),
+        // Generalized cost of the journey in weighted seconds. Times above are clock times.
+        "cost": formatter.cost,
+        // Null if some agency of the journey has no fare data
+        "fare": journey_fare(&network, &formatter.trips),
     });
     Ok(warp::reply::json(&response))
 }
@@ -1,3 +1,4 @@
+use crate::configuration::SearchMode;
 use crate::in_progress_trip::InProgressTrip;
 use crate::time::Time;
 use crate::BusPickupInfo;
@@ -24,34 +25,95 @@ impl Ord for HeapIdTrip {
         self.compare.cmp(&other.compare).reverse()
     }
 }
+/// Number of transfer labels kept in Pareto mode: 0, 1, 2 and 3 or more transfers
+pub const TRANSFER_LABELS: usize = 4;
+
+/// Pareto label of a trip that boarded `boardings` vehicles. Walking only counts as 0 transfers.
+pub fn transfer_label(boardings: u8) -> usize {
+    (boardings.saturating_sub(1) as usize).min(TRANSFER_LABELS - 1)
+}
+
 #[derive(Debug, Default)]
 pub struct TripsArena {
     explore_queue: BinaryHeap<HeapIdTrip>,
-    // TripID -> stop sequence number of boarding
-    trips_already_taken: FxHashMap<IdType, u16>,
+    // (TripID, service day offset, transfer label) -> stop sequence number of boarding (or getting off, for arrive-by)
+    trips_already_taken: FxHashMap<(IdType, i8, usize), u16>,
 
-    // StopID -> Earliest Arrival time
-    stop_arrival_times: FxHashMap<IdType, Time>,
+    // (StopID, transfer label) -> Lowest generalized cost, including transfer penalties, counted from the
+    // search start in search time. Profile searches reuse the arena across departures, so a label from a
+    // later departure only prunes ours if it actually arrives earlier.
+    // The label is always 0 unless keeping Pareto-optimal (cost, transfers) labels.
+    stop_arrival_times: FxHashMap<(IdType, usize), Time>,
     arena: Arena<InProgressTrip>,
+    search_mode: SearchMode,
+    search_start: Time,
+    pareto: bool,
 }
 
 impl TripsArena {
-    pub fn should_explore(&mut self, bu: &BusPickupInfo) -> bool {
-        if let Some(sequence_no) = self.trips_already_taken.get(&bu.trip_id) {
+    pub fn set_search_mode(&mut self, search_mode: SearchMode) {
+        self.search_mode = search_mode;
+    }
+
+    /// Start of the current search, in search time. Costs of the trips explored are relative to it.
+    pub fn set_search_start(&mut self, search_start: Time) {
+        self.search_start = search_start;
+    }
+
+    pub fn search_mode(&self) -> SearchMode {
+        self.search_mode
+    }
+
+    pub fn set_pareto(&mut self, pareto: bool) {
+        self.pareto = pareto;
+    }
+
+    fn label_of(&self, boardings: u8) -> usize {
+        if self.pareto {
+            transfer_label(boardings)
+        } else {
+            0
+        }
+    }
+
+    /// `boardings` counts the vehicles boarded once on `bu`
+    pub fn should_explore(&self, bu: &BusPickupInfo, boardings: u8) -> bool {
+        match self.trips_already_taken.get(&(
+            bu.trip_id,
+            bu.service_day_offset,
+            self.label_of(boardings),
+        ))
+        {
             // Don't get on this trip if we have already boarded on an earlier stop
-            if sequence_no <= &bu.stop_sequence_no {
-                return false;
+            Some(sequence_no) if self.search_mode == SearchMode::DepartAt => {
+                sequence_no > &bu.stop_sequence_no
             }
-        } else {
-            self.trips_already_taken
-                .insert(bu.trip_id, bu.stop_sequence_no);
+            // Don't take this trip if we have already gotten off at a later stop
+            Some(sequence_no) => sequence_no < &bu.stop_sequence_no,
+            None => true,
         }
-        true
+    }
+
+    pub fn mark_trip_taken(&mut self, bu: &BusPickupInfo, boardings: u8) {
+        let label = self.label_of(boardings);
+        self.trips_already_taken
+            .entry((bu.trip_id, bu.service_day_offset, label))
+            .or_insert(bu.stop_sequence_no);
     }
     pub(crate) fn add_to_explore(&mut self, item: InProgressTrip, transfer_cost: u64) -> Option<Id<InProgressTrip>> {
-        println!("Transfer cost {} {}",item.total_transfers, transfer_cost);
-        let score = item.exit_time + Time((item.total_transfers as u64 * transfer_cost) as f64);
-        if let Some(arrival_time) = self.stop_arrival_times.get_mut(&item.get_off_stop_id) {
+        let (frontier_stop, _) = item.frontier(self.search_mode);
+        let score = self.search_start + item.cost + (item.total_transfers as u64 * transfer_cost) as f64;
+        let label = self.label_of(item.total_transfers);
+        // Labels with fewer transfers that arrived earlier dominate us
+        let dominated = (0..label).any(|fewer| {
+            self.stop_arrival_times
+                .get(&(frontier_stop, fewer))
+                .is_some_and(|arrival_time| *arrival_time <= score)
+        });
+        if dominated {
+            return None;
+        }
+        if let Some(arrival_time) = self.stop_arrival_times.get_mut(&(frontier_stop, label)) {
             if *arrival_time <= score {
                 // Someone arrived at this stop before us. Don't explore further.
                 return None;
@@ -60,8 +122,7 @@ impl TripsArena {
                 *arrival_time = score;
             }
         } else {
-            self.stop_arrival_times
-                .insert(item.get_off_stop_id, item.exit_time);
+            self.stop_arrival_times.insert((frontier_stop, label), score);
         }
         let item_exit_time = score;
         let id = self.arena.alloc(item);
@@ -73,6 +134,19 @@ impl TripsArena {
         Some(id)
     }
 
+    /// Queues the trip at an origin. Origins aren't stops, so they never prune each other.
+    pub(crate) fn add_origin(&mut self, item: InProgressTrip) -> Id<InProgressTrip> {
+        let compare = self.search_start + item.cost;
+        let id = self.arena.alloc(item);
+        self.explore_queue.push(HeapIdTrip { compare, inner: id });
+        id
+    }
+
+    /// Stores a trip without queueing it for exploration
+    pub(crate) fn alloc(&mut self, item: InProgressTrip) -> Id<InProgressTrip> {
+        self.arena.alloc(item)
+    }
+
     pub(crate) fn get_by_id(&self, id: Id<InProgressTrip>) -> &InProgressTrip {
         &self.arena[id]
     }
@@ -83,3 +157,39 @@ impl TripsArena {
         Some((self.get_by_id(id).clone(), id))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trip_to_stop(stop_id: IdType, cost: f64) -> InProgressTrip {
+        InProgressTrip {
+            cost,
+            ..InProgressTrip::test_ride((0, 0), stop_id, 0.0, cost)
+        }
+    }
+
+    #[test]
+    fn test_profile_departures_share_labels() {
+        // Profiles search the later departure first, then reuse the arena for the earlier one
+        let later = Time(8.0 * 3600.0 + 600.0);
+        let earlier = Time(8.0 * 3600.0);
+
+        let mut profile = TripsArena::default();
+        profile.set_search_start(later);
+        assert!(profile.add_to_explore(trip_to_stop((0, 1), 600.0), 0).is_some());
+        assert!(profile.add_to_explore(trip_to_stop((0, 2), 300.0), 0).is_some());
+
+        profile.set_search_start(earlier);
+        let mut single = TripsArena::default();
+        single.set_search_start(earlier);
+
+        // Leaving at 8:00 and arriving at 8:15 beats leaving at 8:10 and arriving at 8:20,
+        // even though it takes longer. A search for the 8:00 departure alone keeps it too.
+        assert!(single.add_to_explore(trip_to_stop((0, 1), 900.0), 0).is_some());
+        assert!(profile.add_to_explore(trip_to_stop((0, 1), 900.0), 0).is_some());
+
+        // Arriving at 8:25 is pruned: waiting for the later departure gets there at 8:15
+        assert!(profile.add_to_explore(trip_to_stop((0, 2), 1500.0), 0).is_none());
+    }
+}
@@ -1,14 +1,28 @@
-use crate::agencies::{load_all_gtfs, Agency, City};
+use crate::agencies::{load_all_gtfs, load_feed_versions, Agency, City};
 use bike::Graph;
 
 use futures::StreamExt;
 
-use crate::configuration::Configuration;
+use crate::bike_access::BikeAccess;
+use crate::configuration::{
+    AccessMode, Configuration, CostModel, Exclusions, ModeCost, SearchEngine, SearchMode, TravelMode,
+    WalkingParameters, DEFAULT_MAX_CYCLING_SECS, DEFAULT_MAX_TRANSFERS, MAX_ORIGINS,
+};
 use crate::gtfs_setup::get_agency_id_from_short_name;
+use crate::realtime::service_day_start;
 use bike::{route, RouteResponse, RouteOptions};
-use crate::road_structure::EdgeId;
-use crate::{gtfs_setup, time_to_reach, trip_details, Gtfs1, RoadStructure, Time};
+use crate::road_structure::{EdgeId, EdgeTime};
+use crate::network::Network;
+use crate::scenario::Scenario;
+use crate::profile::EdgeProfile;
+use crate::commute::{CommuteRequest, MAX_DESTINATIONS};
+use crate::feed_diff::DiffRequest;
+use crate::{
+    commute, feed_diff, gtfs_setup, profile, scenario, street_modes, time_to_reach, trip_details, Gtfs1, RoadStructure,
+    Time,
+};
 use gtfs_structure_2::gtfs_wrapper::RouteType;
+use gtfs_structure_2::IdType;
 
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
@@ -21,11 +35,13 @@ use std::path::PathBuf;
 use std::{fmt, io};
 
 use anyhow::{anyhow, Context};
+use chrono::Utc;
+use chrono_tz::Tz;
 use futures::stream::FuturesUnordered;
 use std::sync::Arc;
 
 
-use crate::trip_details::CalculateRequest;
+use crate::trip_details::{CalculateRequest, FeedIdRequest};
 use crate::web_app_data::{AllAppData, CacheKey, CityAppData};
 use crate::web_cache::{check_cache, insert_cache};
 use warp::http::HeaderValue;
@@ -39,8 +55,17 @@ use warp::{Filter, Rejection, Reply};
 
 fn gtfs_to_city_appdata(city: City, gtfs: Gtfs1) -> CityAppData {
     let data = gtfs_setup::generate_stops_trips(&gtfs).into_spatial(&city, &gtfs);
+    let mut scenarios = scenario::load_scenarios(city, &gtfs, &data);
+    for (name, feeds) in load_feed_versions(city) {
+        match Scenario::feed_version(&city, &gtfs, &data, feeds) {
+            Ok(version) => {
+                scenarios.insert(name, version);
+            }
+            Err(e) => log::warn!("Skipping feed version {name}: {e:#}"),
+        }
+    }
 
-    CityAppData::new1(gtfs, data, city)
+    CityAppData::new1(gtfs, data, city, scenarios)
 }
 
 fn check_city(ad: &Arc<AllAppData>, lat: f64, lng: f64) -> Option<City> {
@@ -75,79 +100,390 @@ impl From<&str> for BadQuery {
     }
 }
 
-fn process_coordinates(
-    ad: Arc<AllAppData>,
-    lat: f64,
-    lng: f64,
-    include_agencies: Vec<String>,
-    include_modes: Vec<String>,
-    start_time: u64,
-    max_search_time: f64,
-    transfer_cost_secs: u64,
-) -> Result<Json, BadQuery> {
-    let city = check_city(&ad, lat, lng);
+fn edge_times_to_object(edge_times: Vec<EdgeTime>) -> FxHashMap<EdgeId, u32> {
+    edge_times
+        .into_iter()
+        .map(|edge_time| (edge_time.edge_id, edge_time.time as u32))
+        .collect()
+}
+
+fn walking_parameters(req: &CalculateRequest) -> Result<WalkingParameters, BadQuery> {
+    let in_range = |value: Option<f64>, min: f64, max: f64| {
+        value.map_or(true, |value| (min..=max).contains(&value))
+    };
+    if !in_range(req.walking_speed, 0.3, 3.0)
+        || !in_range(req.max_transfer_walk_m, 0.0, 2000.0)
+        || !in_range(req.max_walking_secs, 60.0, 3600.0)
+        || !in_range(req.snap_distance_m, 10.0, 500.0)
+    {
+        return Err(BadQuery::from("Invalid walking parameters"));
+    }
+
+    let mut walking = WalkingParameters::default();
+    if let Some(speed) = req.walking_speed {
+        walking = walking.with_speed(speed);
+    }
+    walking.max_transfer_distance_m = req
+        .max_transfer_walk_m
+        .unwrap_or(walking.max_transfer_distance_m);
+    walking.max_walking_secs = req.max_walking_secs.unwrap_or(walking.max_walking_secs);
+    walking.snap_distance_m = req.snap_distance_m.unwrap_or(walking.snap_distance_m);
+    if let Some(walk_weight) = req.cost_model.as_ref().and_then(|cost| cost.walk_weight) {
+        walking.cost_weight = walk_weight;
+    }
+    Ok(walking)
+}
+
+// Even-odd rule, treating coordinates as planar
+fn polygon_contains(polygon: &[LatLng], latitude: f64, longitude: f64) -> bool {
+    let mut inside = false;
+    let mut previous = polygon.last().unwrap();
+    for vertex in polygon {
+        if (vertex.latitude > latitude) != (previous.latitude > latitude) {
+            let crossing = vertex.longitude
+                + (latitude - vertex.latitude) / (previous.latitude - vertex.latitude)
+                    * (previous.longitude - vertex.longitude);
+            if longitude < crossing {
+                inside = !inside;
+            }
+        }
+        previous = vertex;
+    }
+    inside
+}
+
+const MAX_EXCLUSIONS: usize = 2000;
+
+fn exclusions(network: &Network, req: &CalculateRequest) -> Result<Exclusions, BadQuery> {
+    let exclusions_req = &req.exclusions;
+    let resolve = |ids: &[FeedIdRequest], kind: &str| -> Result<FxHashSet<IdType>, BadQuery> {
+        if ids.len() > MAX_EXCLUSIONS {
+            return Err(BadQuery::from(format!("Too many excluded {kind}s").as_str()));
+        }
+        ids.iter()
+            .map(|id| {
+                get_agency_id_from_short_name(&id.agency)
+                    .and_then(|agency_id| network.lookup_id(agency_id, &id.id))
+                    .ok_or_else(|| {
+                        BadQuery::from(format!("Unknown {kind} {} of {}", id.id, id.agency).as_str())
+                    })
+            })
+            .collect()
+    };
+
+    let mut exclusions = Exclusions {
+        routes: resolve(&exclusions_req.routes, "route")?,
+        stops: resolve(&exclusions_req.stops, "stop")?,
+        trips: resolve(&exclusions_req.trips, "trip")?,
+        route_stops: FxHashSet::default(),
+    };
+    if let Some(area) = &exclusions_req.avoid_area {
+        if !(3..=200).contains(&area.len()) {
+            return Err(BadQuery::from("Invalid avoid area"));
+        }
+        exclusions.stops.extend(
+            network
+                .stops()
+                .filter(|stop| match (stop.latitude, stop.longitude) {
+                    (Some(latitude), Some(longitude)) => polygon_contains(area, latitude, longitude),
+                    _ => false,
+                })
+                .map(|stop| stop.id),
+        );
+    }
+    Ok(exclusions)
+}
+
+fn cost_model(req: &CalculateRequest) -> Result<CostModel, BadQuery> {
+    let Some(cost_req) = &req.cost_model else {
+        return Ok(CostModel::default());
+    };
+    let valid_weight = |weight: Option<f64>| weight.map_or(true, |w| (0.1..=10.0).contains(&w));
+    let valid_penalty = |penalty: Option<f64>| penalty.map_or(true, |p| (0.0..=1800.0).contains(&p));
+
+    if !valid_weight(cost_req.walk_weight)
+        || !valid_weight(cost_req.wait_weight)
+        || !valid_weight(cost_req.in_vehicle_weight)
+    {
+        return Err(BadQuery::from("Invalid cost weights"));
+    }
+
+    let mut cost = CostModel::default();
+    cost.wait_weight = cost_req.wait_weight.unwrap_or(cost.wait_weight);
+    cost.in_vehicle_weight = cost_req.in_vehicle_weight.unwrap_or(cost.in_vehicle_weight);
+    for (mode, mode_req) in &cost_req.modes {
+        let route_type = RouteType::try_from(mode.as_ref())
+            .map_err(|_| BadQuery::from("Unknown mode in cost model"))?;
+        if !valid_weight(mode_req.weight)
+            || !valid_penalty(mode_req.boarding_penalty_secs)
+            || !valid_penalty(mode_req.alighting_penalty_secs)
+        {
+            return Err(BadQuery::from("Invalid mode cost"));
+        }
+        let default = ModeCost::default();
+        cost.modes.insert(
+            route_type,
+            ModeCost {
+                weight: mode_req.weight.unwrap_or(default.weight),
+                boarding_penalty: mode_req.boarding_penalty_secs.unwrap_or(default.boarding_penalty),
+                alighting_penalty: mode_req.alighting_penalty_secs.unwrap_or(default.alighting_penalty),
+            },
+        );
+    }
+    Ok(cost)
+}
+
+// Validates the request and turns it into the search configuration, for the city of its coordinates
+fn parse_request(
+    ad: &Arc<AllAppData>,
+    req: &CalculateRequest,
+) -> Result<(City, Configuration), BadQuery> {
+    let lat = req.latitude;
+    let lng = req.longitude;
+    let city = check_city(ad, lat, lng);
 
     if city.is_none() {
         return Err(BadQuery::from("Invalid city"));
     }
 
-    if max_search_time >= 3.5 * 3600.0 {
+    if req.max_search_time >= 3.5 * 3600.0 {
         log::warn!("Invalid max search time");
         return Err(BadQuery::from("Invalid max search time"));
     }
+
+    if req.departure_window_secs.is_some_and(|window| window > 2 * 3600) {
+        return Err(BadQuery::from("Invalid departure window"));
+    }
+
+    if req.max_transfers.is_some_and(|max_transfers| max_transfers > 8) {
+        return Err(BadQuery::from("Invalid max transfers"));
+    }
+
+    if req.engine == SearchEngine::Raptor && req.search_mode != SearchMode::DepartAt {
+        return Err(BadQuery::from("The RAPTOR engine only supports departAt searches"));
+    }
+    if req.engine == SearchEngine::Raptor && req.cost_model.is_some() {
+        return Err(BadQuery::from("The RAPTOR engine doesn't support cost models"));
+    }
+    if req.engine == SearchEngine::Raptor && req.max_fare.is_some() {
+        return Err(BadQuery::from("The RAPTOR engine doesn't support a max fare"));
+    }
+    if req.max_fare.is_some_and(|fare| fare.is_nan() || fare < 0.0) {
+        return Err(BadQuery::from("Invalid max fare"));
+    }
+
+    if req.realtime && !cfg!(feature = "realtime") {
+        return Err(BadQuery::from("Realtime feeds aren't enabled on this server"));
+    }
+    if req.realtime && req.search_mode != SearchMode::DepartAt {
+        return Err(BadQuery::from("Realtime only supports departAt searches"));
+    }
+
+    if req.pareto_transfers && req.departure_window_secs.is_some() {
+        return Err(BadQuery::from(
+            "Pareto transfers can't be combined with a departure window",
+        ));
+    }
+    let walking = walking_parameters(req)?;
+    let cost = cost_model(req)?;
     let city = city.unwrap();
-    let ad = &ad.ads.get(&city).unwrap();
 
-    let cache_key = match check_cache(
-        ad,
-        lat,
-        lng,
-        &include_agencies,
-        &include_modes,
-        start_time,
-        max_search_time as u64,
-        transfer_cost_secs
-    ) {
-        Ok(reply) => return Ok(reply),
-        Err(key) => key,
-    };
+    if req.scenario.is_some() && req.realtime {
+        return Err(BadQuery::from("Scenarios can't be combined with realtime feeds"));
+    }
+    let network = ad.ads[&city]
+        .network(req.scenario.as_deref())
+        .ok_or(BadQuery::from("Unknown scenario"))?;
 
-    let gtfs = &ad.gtfs;
-    let spatial_stops = &ad.spatial;
-    let rs_template = ad.rs_template.clone();
-    let mut rs = RoadStructure::new_from_road_structure(rs_template);
+    if req.extra_origins.len() + 1 > MAX_ORIGINS {
+        return Err(BadQuery::from("Too many origins"));
+    }
+    for origin in &req.extra_origins {
+        if check_city(ad, origin.latitude, origin.longitude) != Some(city) {
+            return Err(BadQuery::from("All origins must be in the same city"));
+        }
+    }
 
-    let agency_ids: FxHashSet<u16> = include_agencies
+    if req.travel_mode != TravelMode::Transit
+        && (req.departure_window_secs.is_some()
+            || req.pareto_transfers
+            || req.access_mode != AccessMode::Walk)
+    {
+        return Err(BadQuery::from(
+            "Walking and cycling modes don't support departure windows, Pareto transfers or access modes",
+        ));
+    }
+    if req.travel_mode == TravelMode::Bike && req.search_mode != SearchMode::DepartAt {
+        return Err(BadQuery::from("Cycling only supports departAt searches"));
+    }
+    if req.access_mode == AccessMode::Bike && req.search_mode != SearchMode::DepartAt {
+        return Err(BadQuery::from("Cycling to transit only supports departAt searches"));
+    }
+    if req
+        .max_cycling_secs
+        .is_some_and(|secs| !(60.0..=3600.0).contains(&secs))
+    {
+        return Err(BadQuery::from("Invalid max cycling time"));
+    }
+    // Stop times are in the feeds' local time, so another timezone would pick the wrong service day
+    let timezone = city.get_timezone();
+    if let Some(tz) = &req.timezone {
+        let tz: Tz = tz.parse().map_err(|_| BadQuery::from("Invalid timezone"))?;
+        if tz != timezone {
+            return Err(BadQuery::from("Timezone doesn't match the city's"));
+        }
+    }
+    let service_date = req
+        .service_date
+        .unwrap_or_else(|| Utc::now().with_timezone(&timezone).date_naive());
+
+    let agency_ids: FxHashSet<u16> = req
+        .agencies
         .iter()
         .filter_map(|ag| get_agency_id_from_short_name(ag))
         .collect();
 
-    let modes = include_modes
+    let modes = req
+        .modes
         .iter()
         .filter_map(|x| RouteType::try_from(x.as_ref()).ok())
         .collect();
+    let mut exclusions = exclusions(&network, req)?;
+    // A snapshot: feeds updating during the search don't change it
+    let realtime = req
+        .realtime
+        .then(|| ad.ads[&city].realtime.read().unwrap().clone());
+    let alerts_time = service_day_start(timezone, service_date)
+        .ok_or(BadQuery::from("Service date doesn't exist in the timezone"))?
+        + req.start_time as i64;
+    // Stops, routes and trips without service are skipped like the requested exclusions
+    if let Some(realtime) = &realtime {
+        realtime.add_closures(&mut exclusions, alerts_time);
+    }
 
-    time_to_reach::generate_reach_times(
-        gtfs,
-        spatial_stops,
-        &mut rs,
-        Configuration {
-            start_time: Time(start_time as f64),
-            duration_secs: max_search_time,
-            location: LatLng {
-                latitude: lat,
-                longitude: lng,
-            },
-            agency_ids,
-            transfer_cost: transfer_cost_secs,
-            modes,
+    let config = Configuration {
+        start_time: Time(req.start_time as f64),
+        duration_secs: req.max_search_time,
+        location: LatLng {
+            latitude: lat,
+            longitude: lng,
         },
-    );
+        extra_origins: req.extra_origins.clone(),
+        agency_ids,
+        transfer_cost: req.transfer_cost_secs.unwrap_or(0),
+        modes,
+        service_date,
+        timezone,
+        search_mode: req.search_mode,
+        profile_window_secs: req.departure_window_secs.map(|a| a as f64),
+        wheelchair: req.wheelchair,
+        walking,
+        max_transfers: req.max_transfers.unwrap_or(DEFAULT_MAX_TRANSFERS),
+        pareto_transfers: req.pareto_transfers,
+        engine: req.engine,
+        travel_mode: req.travel_mode,
+        cost,
+        max_fare: req.max_fare,
+        exclusions,
+        realtime,
+        alerts_time,
+        // Only cycled once the cache misses, see `add_bike_access`
+        bike_access: Vec::new(),
+    };
+    Ok((city, config))
+}
+
+/// Runs the cycling isochrones of a bike access request. Kept out of `parse_request` as they are
+/// the most expensive part of it, and cached responses don't need them.
+fn add_bike_access(
+    bikegraph: &Graph,
+    ad: &CityAppData,
+    city: City,
+    req: &CalculateRequest,
+    config: &mut Configuration,
+) -> Result<(), BadQuery> {
+    if req.access_mode != AccessMode::Bike {
+        return Ok(());
+    }
+    let network = ad.network(req.scenario.as_deref()).unwrap();
+    let max_cycling_secs = req.max_cycling_secs.unwrap_or(DEFAULT_MAX_CYCLING_SECS);
+    config.bike_access = config
+        .origins()
+        .map(|origin| {
+            BikeAccess::new(
+                bikegraph,
+                &city,
+                &network,
+                origin,
+                max_cycling_secs,
+                config.walking.speed,
+            )
+            .map(Arc::new)
+            .ok_or_else(|| BadQuery::from("No bike network near the origin"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(())
+}
+
+fn process_coordinates(ad: Arc<AllAppData>, req: CalculateRequest) -> Result<Json, BadQuery> {
+    let (city, mut config) = parse_request(&ad, &req)?;
+    let bikegraph = &ad.bikegraph;
+    let ad = &ad.ads.get(&city).unwrap();
+
+    let realtime_version = config.realtime.as_ref().map(|realtime| realtime.version);
+    let cache_key = match check_cache(ad, &req, config.service_date, config.timezone, realtime_version) {
+        Ok(reply) => return Ok(reply),
+        Err(key) => key,
+    };
+    add_bike_access(bikegraph, ad, city, &req, &mut config)?;
+
+    let search_start = config.search_mode.to_search_time(config.start_time);
+    let network = ad.network(req.scenario.as_deref()).unwrap();
+    let rs_template = ad.rs_template.clone();
+    let mut rs = RoadStructure::new_from_road_structure(rs_template);
+    rs.scenario = req.scenario.clone();
+
+    let edge_profiles = match config.travel_mode {
+        TravelMode::Transit if config.profile_window_secs.is_some() => {
+            Some(profile::generate_profile(&network, &mut rs, config))
+        }
+        TravelMode::Transit => {
+            time_to_reach::generate_reach_times(&network, &mut rs, config);
+            None
+        }
+        TravelMode::Walk => {
+            street_modes::generate_walking_times(&mut rs, config);
+            None
+        }
+        TravelMode::Bike => {
+            if !street_modes::generate_cycling_times(&mut rs, bikegraph, config) {
+                return Err(BadQuery::from("No bike network near the origin"));
+            }
+            None
+        }
+    };
 
     let edge_times = rs.save();
-    let edge_times_object: FxHashMap<EdgeId, u32> = edge_times
+    // Edge ID -> generalized cost from the origin, in weighted seconds
+    let edge_costs: Option<FxHashMap<EdgeId, u32>> = req.cost_model.is_some().then(|| {
+        edge_times
+            .iter()
+            .map(|edge_time| (edge_time.edge_id, (edge_time.cost - search_start.0) as u32))
+            .collect()
+    });
+    // Edge ID -> index of the origin reaching it first, 0 being `latitude`/`longitude`
+    let edge_origins: Option<FxHashMap<EdgeId, u8>> = (!req.extra_origins.is_empty()).then(|| {
+        edge_times
+            .iter()
+            .map(|edge_time| (edge_time.edge_id, edge_time.origin))
+            .collect()
+    });
+    let edge_times_object = edge_times_to_object(edge_times);
+    // [at most 0 transfers, at most 1, at most 2, any], each Edge ID -> time
+    let edge_times_by_transfers: Vec<FxHashMap<EdgeId, u32>> = rs
+        .save_by_transfers()
         .into_iter()
-        .map(|edge_time| (edge_time.edge_id, edge_time.time as u32))
+        .map(edge_times_to_object)
         .collect();
 
     let rs_list_index = ad.rs_list.write().unwrap().push(rs);
@@ -155,14 +491,126 @@ fn process_coordinates(
         rs_list_index,
         city,
     };
-    let response = json!({
+    let mut response = json!({
         "request_id": request_id,
         "edge_times": edge_times_object
     });
 
+    if let Some(edge_origins) = edge_origins {
+        response["edge_origins"] = json!(edge_origins);
+    }
+
+    if let Some(edge_costs) = edge_costs {
+        response["edge_costs"] = json!(edge_costs);
+    }
+
+    if !edge_times_by_transfers.is_empty() {
+        response["edge_times_by_transfers"] = json!(edge_times_by_transfers);
+    }
+
+    if let Some(edge_profiles) = edge_profiles {
+        // Edge ID -> [min, median, 90th percentile] travel time in seconds
+        let edge_stats: FxHashMap<EdgeId, EdgeProfile> = edge_profiles
+            .into_iter()
+            .map(|profile| (profile.edge_id, profile))
+            .collect();
+        response["edge_stats"] = json!(edge_stats);
+    }
+
     Ok(insert_cache(cache_key, response, request_id))
 }
 
+/// Unlike `/hello`, commute scores aren't cached and have no request ID: the road structures of each
+/// destination's search are dropped once combined, so there is no single search to get trip details from.
+fn process_commute(ad: Arc<AllAppData>, req: CommuteRequest) -> Result<Json, BadQuery> {
+    if req.destinations.is_empty() || req.destinations.len() > MAX_DESTINATIONS {
+        return Err(BadQuery::from("Invalid number of destinations"));
+    }
+    if req
+        .destinations
+        .iter()
+        .any(|destination| !destination.weight.is_finite() || destination.weight < 0.0)
+    {
+        return Err(BadQuery::from("Invalid destination weight"));
+    }
+    if req.search.departure_window_secs.is_some()
+        || req.search.pareto_transfers
+        || req.search.travel_mode != TravelMode::Transit
+    {
+        return Err(BadQuery::from(
+            "Commute scores don't support departure windows, Pareto transfers or walking and cycling modes",
+        ));
+    }
+
+    let mut city = None;
+    let mut configs = Vec::new();
+    for destination in &req.destinations {
+        let search = CalculateRequest {
+            latitude: destination.latitude,
+            longitude: destination.longitude,
+            ..req.search.clone()
+        };
+        let (destination_city, mut config) = parse_request(&ad, &search)?;
+        if city.is_some_and(|city| city != destination_city) {
+            return Err(BadQuery::from("All destinations must be in the same city"));
+        }
+        add_bike_access(&ad.bikegraph, &ad.ads[&destination_city], destination_city, &search, &mut config)?;
+        city = Some(destination_city);
+        configs.push(config);
+    }
+    let ad = &ad.ads.get(&city.unwrap()).unwrap();
+    let network = ad.network(req.search.scenario.as_deref()).unwrap();
+
+    let scores = commute::generate_commute_scores(
+        &network,
+        &ad.rs_template,
+        configs,
+        &req.destinations,
+        req.combine,
+    );
+    let edge_times_object: FxHashMap<EdgeId, u32> = scores
+        .into_iter()
+        .map(|(edge_id, score)| (edge_id, score as u32))
+        .collect();
+
+    Ok(warp::reply::json(&json!({
+        "edge_times": edge_times_object
+    })))
+}
+
+fn process_diff(ad: Arc<AllAppData>, req: DiffRequest) -> Result<Json, BadQuery> {
+    if req.search.departure_window_secs.is_some()
+        || req.search.pareto_transfers
+        || req.search.travel_mode != TravelMode::Transit
+    {
+        return Err(BadQuery::from(
+            "Diffs don't support departure windows, Pareto transfers or walking and cycling modes",
+        ));
+    }
+    if req.before == req.after {
+        return Err(BadQuery::from("Diffs need two different networks"));
+    }
+
+    let mut travel_times = Vec::with_capacity(2);
+    for scenario in [&req.before, &req.after] {
+        let search = CalculateRequest {
+            scenario: scenario.clone(),
+            ..req.search.clone()
+        };
+        let (city, mut config) = parse_request(&ad, &search)?;
+        let city_ad = &ad.ads[&city];
+        add_bike_access(&ad.bikegraph, city_ad, city, &search, &mut config)?;
+        let network = city_ad.network(scenario.as_deref()).unwrap();
+        travel_times.push(feed_diff::travel_times(&network, &city_ad.rs_template, config));
+    }
+    let (edge_deltas, summary) = feed_diff::diff_travel_times(&travel_times[0], &travel_times[1]);
+
+    Ok(warp::reply::json(&json!({
+        "edge_deltas": edge_deltas,
+        "summary": summary
+    })))
+}
+
 #[derive(Deserialize, Clone, Copy)]
 pub struct LatLng {
     pub latitude: f64,
@@ -188,7 +636,7 @@ impl LatLng {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct RequestId {
     pub rs_list_index: CacheKey,
     pub city: City,
@@ -317,6 +765,9 @@ pub async fn main() {
     }
     let appdata = Arc::new(AllAppData { ads: all_gtfs, bikegraph: Graph::new() });
 
+    #[cfg(feature = "realtime")]
+    tokio::spawn(crate::gtfs_rt::poll_feeds(appdata.clone(), agencies.clone()));
+
     let bike_endpoint = bike_endpoints(appdata.clone());
 
     let cors_policy = warp::cors()
@@ -338,18 +789,27 @@ pub async fn main() {
         .and(with_appdata(appdata.clone()))
         .and(warp::path!("hello"))
         .and(warp::body::json())
-        .map(|ad: Arc<AllAppData>, req: CalculateRequest| {
-            process_coordinates(
-                ad,
-                req.latitude,
-                req.longitude,
-                req.agencies,
-                req.modes,
-                req.start_time,
-                req.max_search_time,
-                req.transfer_cost_secs.unwrap_or(0)
-            )
-        })
+        .map(process_coordinates)
+        .map(|r: Result<Json, BadQuery>| match r {
+            Ok(a) => warp::reply::with_status(a, StatusCode::OK).into_response(),
+            Err(e) => warp::reply::with_status(e.reason, StatusCode::BAD_REQUEST).into_response(),
+        });
+
+    let commute = warp::post()
+        .and(with_appdata(appdata.clone()))
+        .and(warp::path!("commute"))
+        .and(warp::body::json())
+        .map(process_commute)
+        .map(|r: Result<Json, BadQuery>| match r {
+            Ok(a) => warp::reply::with_status(a, StatusCode::OK).into_response(),
+            Err(e) => warp::reply::with_status(e.reason, StatusCode::BAD_REQUEST).into_response(),
+        });
+
+    let diff = warp::post()
+        .and(with_appdata(appdata.clone()))
+        .and(warp::path!("diff"))
+        .and(warp::body::json())
+        .map(process_diff)
         .map(|r: Result<Json, BadQuery>| match r {
             Ok(a) => warp::reply::with_status(a, StatusCode::OK).into_response(),
             Err(e) => warp::reply::with_status(e.reason, StatusCode::BAD_REQUEST).into_response(),
@@ -414,6 +874,8 @@ pub async fn main() {
         .or(details)
         .or(mvt_endpoint)
         .or(hello)
+        .or(commute)
+        .or(diff)
         .or(bike_endpoint)
         .with(cors_policy)
         .with(log);
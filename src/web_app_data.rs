@@ -1,5 +1,8 @@
 use crate::gtfs_processing::SpatialStopsWithTrips;
+use crate::network::Network;
+use crate::realtime::RealtimeOverlay;
 use crate::road_structure::RoadStructureInner;
+use crate::scenario::Scenario;
 use crate::{City, Gtfs1, RoadStructure};
 use lru::LruCache;
 use rustc_hash::FxHashMap;
@@ -12,6 +15,10 @@ pub struct CityAppData {
     pub spatial: SpatialStopsWithTrips,
     pub rs_template: Arc<RoadStructureInner>,
     pub rs_list: RwLock<RoadStructureList>,
+    /// Latest realtime overlay. Replaced as a whole when feeds update, while searches keep their own [Arc].
+    pub realtime: RwLock<Arc<RealtimeOverlay>>,
+    /// What-if scenarios and other versions of feeds by ID, laid over `gtfs` and `spatial`
+    pub scenarios: FxHashMap<String, Scenario>,
 }
 
 pub struct AllAppData {
@@ -20,15 +27,35 @@ pub struct AllAppData {
 }
 
 impl CityAppData {
-    pub(crate) fn new1(gtfs: Gtfs1, spatial: SpatialStopsWithTrips, city: City) -> CityAppData {
+    pub(crate) fn new1(
+        gtfs: Gtfs1,
+        spatial: SpatialStopsWithTrips,
+        city: City,
+        scenarios: FxHashMap<String, Scenario>,
+    ) -> CityAppData {
         let rs = RoadStructureInner::new(city);
         CityAppData {
             gtfs,
             spatial,
             rs_template: Arc::new(rs),
             rs_list: RwLock::new(RoadStructureList::new()),
+            realtime: RwLock::new(Arc::new(RealtimeOverlay::default())),
+            scenarios,
         }
     }
+
+    /// Network of the scenario, or of the city itself. None if there's no such scenario.
+    pub fn network(&self, scenario: Option<&str>) -> Option<Network<'_>> {
+        let scenario = match scenario {
+            Some(scenario) => Some(self.scenarios.get(scenario)?),
+            None => None,
+        };
+        Some(Network {
+            gtfs: &self.gtfs,
+            spatial: &self.spatial,
+            scenario,
+        })
+    }
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -1,5 +1,8 @@
+use crate::trip_details::CalculateRequest;
 use crate::web::RequestId;
 use crate::web_app_data::CityAppData;
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
 use lru::LruCache;
 use serde_json::value::Value;
@@ -23,60 +26,115 @@ lazy_static! {
     static ref CACHE: Mutex<LruCache<u64, CacheValue>> =
         Mutex::new(LruCache::new(NonZeroUsize::new(30).unwrap()));
 }
-fn round_f64_for_hash(x: f64) -> u64 {
-    (x * 10000.0).round() as u64
+// Signed, so negative longitudes and latitudes don't all saturate to 0
+fn round_f64_for_hash(x: f64) -> i64 {
+    (x * 10000.0).round() as i64
 }
 
 fn cache_key(
-    lat: f64,
-    lng: f64,
-    include_agencies: &[String],
-    include_modes: &[String],
-    start_time: u64,
-    max_duration_secs: u64,
-    transfer_costs: u64,
+    req: &CalculateRequest,
+    service_date: NaiveDate,
+    timezone: Tz,
+    realtime_version: Option<u64>,
 ) -> u64 {
     let mut hasher = DefaultHasher::new();
-    hasher.write_u64(round_f64_for_hash(lat));
-    hasher.write_u64(round_f64_for_hash(lng));
+    hasher.write_i64(round_f64_for_hash(req.latitude));
+    hasher.write_i64(round_f64_for_hash(req.longitude));
+    "ORIGINS".hash(&mut hasher);
+    for origin in &req.extra_origins {
+        hasher.write_i64(round_f64_for_hash(origin.latitude));
+        hasher.write_i64(round_f64_for_hash(origin.longitude));
+    }
 
     "AGENCY".hash(&mut hasher);
-    for agency in include_agencies {
+    for agency in &req.agencies {
         agency.hash(&mut hasher);
     }
 
     "MODE".hash(&mut hasher);
-    for mode in include_modes {
+    for mode in &req.modes {
         mode.hash(&mut hasher);
     }
 
-    start_time.hash(&mut hasher);
-    max_duration_secs.hash(&mut hasher);
+    req.start_time.hash(&mut hasher);
+    (req.max_search_time as u64).hash(&mut hasher);
+
+    req.transfer_cost_secs.unwrap_or(0).hash(&mut hasher);
+    req.search_mode.hash(&mut hasher);
+    req.departure_window_secs.hash(&mut hasher);
+    req.wheelchair.hash(&mut hasher);
+    req.max_transfers.hash(&mut hasher);
+    req.pareto_transfers.hash(&mut hasher);
+    req.engine.hash(&mut hasher);
+    req.travel_mode.hash(&mut hasher);
+    req.access_mode.hash(&mut hasher);
+    req.max_cycling_secs.map(round_f64_for_hash).hash(&mut hasher);
+    req.max_fare.map(round_f64_for_hash).hash(&mut hasher);
+
+    "WALKING".hash(&mut hasher);
+    for parameter in [
+        req.walking_speed,
+        req.max_transfer_walk_m,
+        req.max_walking_secs,
+        req.snap_distance_m,
+    ] {
+        parameter.map(round_f64_for_hash).hash(&mut hasher);
+    }
+
+    "COST".hash(&mut hasher);
+    if let Some(cost) = &req.cost_model {
+        for weight in [cost.walk_weight, cost.wait_weight, cost.in_vehicle_weight] {
+            weight.map(round_f64_for_hash).hash(&mut hasher);
+        }
+        for (mode, mode_cost) in &cost.modes {
+            mode.hash(&mut hasher);
+            for parameter in [
+                mode_cost.weight,
+                mode_cost.boarding_penalty_secs,
+                mode_cost.alighting_penalty_secs,
+            ] {
+                parameter.map(round_f64_for_hash).hash(&mut hasher);
+            }
+        }
+    }
+
+    "EXCLUSIONS".hash(&mut hasher);
+    let exclusions = &req.exclusions;
+    for ids in [&exclusions.routes, &exclusions.stops, &exclusions.trips] {
+        ids.len().hash(&mut hasher);
+        for id in ids {
+            id.agency.hash(&mut hasher);
+            id.id.hash(&mut hasher);
+        }
+    }
+    if let Some(area) = &exclusions.avoid_area {
+        for vertex in area {
+            hasher.write_i64(round_f64_for_hash(vertex.latitude));
+            hasher.write_i64(round_f64_for_hash(vertex.longitude));
+        }
+    }
+
+    "REALTIME".hash(&mut hasher);
+    realtime_version.hash(&mut hasher);
+
+    "SCENARIO".hash(&mut hasher);
+    req.scenario.hash(&mut hasher);
 
-    transfer_costs.hash(&mut hasher);
+    "DATE".hash(&mut hasher);
+    service_date.num_days_from_ce().hash(&mut hasher);
+    timezone.name().hash(&mut hasher);
     hasher.finish()
 }
 
-pub fn check_cache<'a>(
+pub fn check_cache(
     ad: &CityAppData,
-    lat: f64,
-    lng: f64,
-    include_agencies: &[String],
-    include_modes: &[String],
-    start_time: u64,
-    max_duration_secs: u64,
-    transfer_costs: u64
+    req: &CalculateRequest,
+    service_date: NaiveDate,
+    timezone: Tz,
+    realtime_version: Option<u64>,
 ) -> Result<Json, u64> {
     let mut cache = CACHE.lock().unwrap();
-    let hash = cache_key(
-        lat,
-        lng,
-        include_agencies,
-        include_modes,
-        start_time,
-        max_duration_secs,
-        transfer_costs
-    );
+    let hash = cache_key(req, service_date, timezone, realtime_version);
     cache
         .get(&hash)
         .and_then(|x| {